@@ -1,408 +1,2543 @@
 use rust_decimal::prelude::*;
+use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
-use std::collections::HashSet;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::rc::Rc;
 
+use crate::audit::AuditIssue;
+use crate::config::{BalancePolicy, Config, FeeRefundPolicy, TransferDisputePolicy};
+use crate::error::TransactionError;
+use crate::handler::TransactionHandler;
+use crate::ledger::{EntryKind, LedgerEntry};
+use crate::snapshot::AccountRow;
 use crate::transaction::{Transaction, TransactionType};
 
+/// The number of seconds in a day.
+const DAY_SECONDS: i64 = 86400;
+
+/// The flag raised when the available funds go below zero
+/// under the `AllowAndFlag` balance policy.
+pub const OVERDRAWN_FLAG: &str = "overdrawn";
+
+/// The flag raised when a fraud rule puts the account on hold. The
+/// debits of an account on hold are rejected until it is reviewed.
+pub const HOLD_FLAG: &str = "hold";
+
+/// The funds of an account in a single currency.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Default)]
+#[derive(PartialEq)]
+#[derive(Serialize)]
+#[derive(Deserialize)]
+pub struct Balance {
+    pub available: Decimal,
+
+    pub held: Decimal,
+
+    /// The funds reserved by authorizations that were not captured yet.
+    pub reserved: Decimal,
+}
+impl Balance {
+    pub fn get_total(&self) -> Decimal {
+        self.available + self.held + self.reserved
+    }
+}
+
+/// The state of the disputes of a transaction. A transaction can be
+/// disputed several times, as long as the amounts held and reversed
+/// do not exceed the amount of the transaction.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Default)]
+#[derive(PartialEq)]
+#[derive(Serialize)]
+#[derive(Deserialize)]
+pub struct Dispute {
+    /// The amount currently held.
+    pub held: Decimal,
+
+    /// The amount charged back.
+    pub reversed: Decimal,
+}
+
+/// Funds reserved for a later capture.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+#[derive(Serialize)]
+#[derive(Deserialize)]
+pub struct Authorization {
+    pub currency: String,
+
+    pub amount: Decimal,
+
+    /// The number of rows processed by the bank when the funds were reserved.
+    pub row: u64,
+
+    /// The time at which the funds were reserved, if known.
+    pub timestamp: Option<i64>,
+}
+
+/// A disputed transaction whose funds are still held.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub struct OpenDispute {
+    pub client_id: u16,
+
+    pub transaction_id: u32,
+
+    pub currency: String,
+
+    pub held: Decimal,
+}
+
+/// Funds withdrawn or transferred from an account, used to enforce the limits.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Serialize)]
+#[derive(Deserialize)]
+struct Debit {
+    timestamp: i64,
+
+    currency: String,
+
+    amount: Decimal,
+}
+
+/// A view of an account given to the handlers of custom transaction types.
+/// The funds can only be moved through credits and debits, which are
+/// recorded in the history of the account, and debits follow the same
+/// rules as withdrawals.
+pub struct AccountView<'a> {
+    account: &'a mut Account,
+
+    // The transaction being processed.
+    tx: &'a Transaction,
+}
+impl AccountView<'_> {
+    pub fn get_client_id(&self) -> u16 {
+        self.account.client_id
+    }
+
+    /// Gets the currency of the transaction being processed.
+    pub fn get_currency(&self) -> String {
+        self.account.get_currency(self.tx)
+    }
+
+    /// Gets the amount of the transaction being processed, which cannot be
+    /// negative. The amount is rounded to the precision of its currency.
+    pub fn get_amount(&self) -> Result<Decimal, TransactionError> {
+        self.account.get_amount(self.tx, &self.get_currency())
+    }
+
+    pub fn get_balance(&self, currency: &str) -> Balance {
+        self.account.get_balance(currency)
+    }
+
+    /// Gets a transaction that was processed by the account.
+    pub fn get_transaction(&self, transaction_id: u32) -> Option<&Transaction> {
+        self.account.transactions.get(&transaction_id)
+    }
+
+    pub fn raise_flag(&mut self, flag: &str) {
+        self.account.raise_flag(flag);
+    }
+
+    /// Credits the available funds of the account.
+    pub fn credit(&mut self, currency: &str, amount: Decimal) -> Result<(), TransactionError> {
+        if amount < Decimal::ZERO {
+            return Err(TransactionError::InvalidAmount(amount));
+        }
+        let currency = currency.to_uppercase();
+        self.account.get_balance_mut(&currency).available += amount;
+        self.account
+            .record(self.tx, EntryKind::Transaction, &currency, amount, Decimal::ZERO);
+        Ok(())
+    }
+
+    /// Debits the available funds of the account, according to its
+    /// balance policy. The debits of an account on hold are rejected.
+    pub fn debit(&mut self, currency: &str, amount: Decimal) -> Result<(), TransactionError> {
+        if amount < Decimal::ZERO {
+            return Err(TransactionError::InvalidAmount(amount));
+        }
+        let currency = currency.to_uppercase();
+        self.account.check_hold()?;
+        self.account.check_debit(&currency, amount)?;
+        self.account.debit(&currency, amount);
+        self.account
+            .record(self.tx, EntryKind::Transaction, &currency, -amount, Decimal::ZERO);
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+#[derive(Serialize)]
+#[derive(Deserialize)]
 pub struct Account {
     client_id: u16,
 
-    available: Decimal,
-
-    held: Decimal,
+    // The funds of the account, indexed by currency.
+    balances: BTreeMap<String, Balance>,
 
     locked: bool,
 
+    // The flags raised on this account, for manual review.
+    flags: BTreeSet<String>,
+
     // A cache of the transactions that were processed
-    // for this account.
+    // for this account. The amount of the cached transactions is
+    // rounded, their currency is always set, and their timestamp is set
+    // as soon as the account processed a timestamped transaction.
     transactions: BTreeMap<u32, Transaction>,
 
-    // A cache of the IDs of the disputed transactions.
-    pub disputed_transactions: HashSet<u32>,
+    // The amounts held and reversed of the transactions that
+    // were disputed, indexed by transaction ID.
+    pub disputed_transactions: HashMap<u32, Dispute>,
+
+    // The amounts reversed or refunded of the transactions of this
+    // account, indexed by transaction ID.
+    reversals: HashMap<u32, Decimal>,
+
+    // The authorizations that were not captured, voided or expired,
+    // indexed by transaction ID.
+    authorizations: BTreeMap<u32, Authorization>,
+
+    // The number of rows processed by the bank when this account
+    // last processed a transaction.
+    row: u64,
+
+    // The timestamp of the last transaction processed by this account.
+    last_timestamp: Option<i64>,
+
+    // The recent withdrawals and transfers of this account, oldest first.
+    debits: VecDeque<Debit>,
+
+    // The fees charged for the transactions of this account,
+    // indexed by transaction ID.
+    fees: HashMap<u32, Decimal>,
+
+    // Every movement of funds applied to this account, in order.
+    history: Vec<LedgerEntry>,
+
+    #[serde(skip)]
+    config: Rc<Config>,
 }
 impl Account {
     pub fn new(client_id: u16) -> Account {
         Account {
             client_id,
-            held: Decimal::from_str("0.0").unwrap(),
-            available: Decimal::from_str("0.0").unwrap(),
+            balances: BTreeMap::new(),
             locked: false,
+            flags: BTreeSet::new(),
             transactions: BTreeMap::new(),
-            disputed_transactions: HashSet::new(),
+            disputed_transactions: HashMap::new(),
+            reversals: HashMap::new(),
+            authorizations: BTreeMap::new(),
+            row: 0,
+            last_timestamp: None,
+            debits: VecDeque::new(),
+            fees: HashMap::new(),
+            history: vec![],
+            config: Rc::new(Config::default()),
+        }
+    }
+
+    pub fn with_config(client_id: u16, config: Rc<Config>) -> Account {
+        Account {
+            config,
+            ..Account::new(client_id)
         }
     }
 
-    pub fn process_transaction(&mut self, tx: Transaction) {
+    /// Sets the configuration of an account loaded from a saved state,
+    /// which does not include it.
+    pub fn set_config(&mut self, config: Rc<Config>) {
+        self.config = config;
+    }
+
+    /// Processes a transaction, and returns the movements of funds
+    /// that it caused, fees included. Only the source side of a transfer
+    /// is processed here; crediting the destination is up to the bank.
+    pub fn process_transaction(&mut self, mut tx: Transaction) -> Result<Vec<LedgerEntry>, TransactionError> {
         // Currently there is no way to process a transaction once the
         // account was locked.
         if self.locked {
-            eprintln!("Could not process transaction: The account is locked.");
-            return;
+            return Err(TransactionError::AccountLocked);
         }
+        let timestamp = self.get_timestamp(&tx)?;
+        let history_start = self.history.len();
 
         match tx.get_type() {
             TransactionType::Deposit => {
                 if self.transactions.contains_key(&tx.transaction_id) {
-                    return;
+                    return Err(TransactionError::DuplicateTransaction(tx.transaction_id));
+                }
+                let currency = self.get_currency(&tx);
+                let amount = self.get_amount(&tx, &currency)?;
+                let fee = self.get_fee(&tx, &currency, amount);
+
+                if fee > amount {
+                    self.check_debit(&currency, fee - amount)?;
                 }
 
                 // TODO make this critical section atomic.
                 // BEGIN CRITICAL SECTION
-                self.available += tx.amount.unwrap();
+                self.get_balance_mut(&currency).available += amount;
+                self.record(&tx, EntryKind::Transaction, &currency, amount, Decimal::ZERO);
+                self.charge_fee(&tx, &currency, fee);
+                tx.amount = Some(amount);
+                tx.currency = Some(currency);
+                tx.timestamp = timestamp;
                 self.transactions.insert(tx.transaction_id, tx);
                 // END CRITICAL SECTION
             }
-            TransactionType::Withdrawal => {
+            TransactionType::Withdrawal | TransactionType::Transfer => {
                 if self.transactions.contains_key(&tx.transaction_id) {
-                    return;
+                    return Err(TransactionError::DuplicateTransaction(tx.transaction_id));
                 }
+                let currency = self.get_currency(&tx);
+                let amount = self.get_amount(&tx, &currency)?;
+                let fee = self.get_fee(&tx, &currency, amount);
 
-                if self.available < tx.amount.unwrap() {
-                    eprintln!("Could not process transaction: Insufficient amount.");
-                    return;
-                }
+                self.check_hold()?;
+                self.check_debit(&currency, amount + fee)?;
+                self.check_limits(timestamp, &currency, amount)?;
 
                 // TODO make this critical section atomic.
                 // BEGIN CRITICAL SECTION
-                self.available -= tx.amount.unwrap();
+                self.debit(&currency, amount);
+                self.record(&tx, EntryKind::Transaction, &currency, -amount, Decimal::ZERO);
+                self.charge_fee(&tx, &currency, fee);
+                if let Some(timestamp) = timestamp {
+                    self.debits.push_back(Debit {
+                        timestamp,
+                        currency: currency.clone(),
+                        amount,
+                    });
+                }
+                tx.amount = Some(amount);
+                tx.currency = Some(currency);
+                tx.timestamp = timestamp;
                 self.transactions.insert(tx.transaction_id, tx);
                 // END CRITICAL SECTION
             }
             TransactionType::Dispute => {
                 let disputed_tx = match self.transactions.get(&tx.transaction_id) {
                     Some(tx) => tx,
-                    None => return,
+                    None => return Err(TransactionError::UnknownTransaction(tx.transaction_id)),
+                };
+
+                if !self.is_disputable(disputed_tx) {
+                    return Err(TransactionError::NotDisputable(tx.transaction_id));
+                }
+
+                if let (Some(window), Some(now), Some(then)) = (
+                    self.config.dispute_window_seconds,
+                    timestamp,
+                    disputed_tx.timestamp,
+                ) {
+                    if now.saturating_sub(then) > window {
+                        return Err(TransactionError::DisputeWindowExpired(tx.transaction_id));
+                    }
+                }
+
+                // Disputes always apply in the currency of the disputed transaction.
+                let currency = self.get_currency(disputed_tx);
+                let dispute = self.get_dispute(tx.transaction_id);
+                let remaining = disputed_tx.amount.unwrap()
+                    - dispute.held
+                    - dispute.reversed
+                    - self.get_reversed_amount(tx.transaction_id);
+                if remaining.is_zero() {
+                    return Err(TransactionError::AlreadyDisputed(tx.transaction_id));
+                }
+                let amount = self.get_partial_amount(&tx, &currency, remaining)?;
+                self.check_debit(&currency, amount)?;
+
+                // TODO make this critical section atomic.
+                // BEGIN CRITICAL SECTION
+                self.debit(&currency, amount);
+                self.get_balance_mut(&currency).held += amount;
+                self.record(&tx, EntryKind::Transaction, &currency, -amount, amount);
+                self.disputed_transactions
+                    .entry(tx.transaction_id)
+                    .or_default()
+                    .held += amount;
+                // END CRITICAL SECTION
+            }
+            TransactionType::Resolve => {
+                let (currency, held) = self.get_disputed_amount(&tx)?;
+                let amount = self.get_partial_amount(&tx, &currency, held)?;
+
+                // TODO make this critical section atomic.
+                // BEGIN CRITICAL SECTION
+                let balance = self.get_balance_mut(&currency);
+                balance.available += amount;
+                balance.held -= amount;
+                self.record(&tx, EntryKind::Transaction, &currency, amount, -amount);
+                self.disputed_transactions
+                    .get_mut(&tx.transaction_id)
+                    .unwrap()
+                    .held -= amount;
+                // END CRITICAL SECTION
+            }
+            TransactionType::Chargeback => {
+                let (currency, held) = self.get_disputed_amount(&tx)?;
+                let amount = self.get_partial_amount(&tx, &currency, held)?;
+
+                // TODO make this critical section atomic.
+                // BEGIN CRITICAL SECTION
+                self.get_balance_mut(&currency).held -= amount;
+                self.record(&tx, EntryKind::Transaction, &currency, Decimal::ZERO, -amount);
+                let dispute = self.disputed_transactions.get_mut(&tx.transaction_id).unwrap();
+                dispute.held -= amount;
+                dispute.reversed += amount;
+                if self.config.fees.chargeback == FeeRefundPolicy::Refund {
+                    self.refund_fee(&tx, &currency, amount);
+                }
+                self.locked = true;
+                // END CRITICAL SECTION
+            }
+            TransactionType::Reversal | TransactionType::Refund => {
+                let reversed_tx = match self.transactions.get(&tx.transaction_id) {
+                    Some(tx) => tx,
+                    None => return Err(TransactionError::UnknownTransaction(tx.transaction_id)),
                 };
 
-                if !disputed_tx.is_disputable() {
-                    eprintln!("Transaction {} is not disputable.", disputed_tx.transaction_id);
-                    return;
+                let reversible_types = match tx.get_type() {
+                    TransactionType::Refund => vec![TransactionType::Withdrawal],
+                    _ => vec![TransactionType::Deposit, TransactionType::Withdrawal],
+                };
+                if !reversible_types.contains(reversed_tx.get_type()) {
+                    return Err(TransactionError::NotReversible(tx.transaction_id));
+                }
+
+                if self.reversals.contains_key(&tx.transaction_id) {
+                    return Err(TransactionError::AlreadyReversed(tx.transaction_id));
+                }
+
+                let dispute = self.get_dispute(tx.transaction_id);
+                if !dispute.held.is_zero() {
+                    return Err(TransactionError::UnderDispute(tx.transaction_id));
+                }
+
+                let currency = self.get_currency(reversed_tx);
+                let is_deposit = *reversed_tx.get_type() == TransactionType::Deposit;
+                let amount =
+                    self.get_partial_amount(&tx, &currency, reversed_tx.amount.unwrap() - dispute.reversed)?;
+                if is_deposit {
+                    self.check_debit(&currency, amount)?;
+                }
+
+                // TODO make this critical section atomic.
+                // BEGIN CRITICAL SECTION
+                if is_deposit {
+                    self.debit(&currency, amount);
+                    self.record(&tx, EntryKind::Transaction, &currency, -amount, Decimal::ZERO);
+                } else {
+                    self.get_balance_mut(&currency).available += amount;
+                    self.record(&tx, EntryKind::Transaction, &currency, amount, Decimal::ZERO);
+                }
+                self.reversals.insert(tx.transaction_id, amount);
+                // END CRITICAL SECTION
+            }
+            TransactionType::Authorize => {
+                if self.transactions.contains_key(&tx.transaction_id) {
+                    return Err(TransactionError::DuplicateTransaction(tx.transaction_id));
+                }
+                let currency = self.get_currency(&tx);
+                let amount = self.get_amount(&tx, &currency)?;
+
+                self.check_hold()?;
+                self.check_debit(&currency, amount)?;
+
+                // TODO make this critical section atomic.
+                // BEGIN CRITICAL SECTION
+                self.debit(&currency, amount);
+                self.get_balance_mut(&currency).reserved += amount;
+                self.record_reserved(
+                    tx.transaction_id,
+                    TransactionType::Authorize,
+                    EntryKind::Transaction,
+                    &currency,
+                    amount,
+                );
+                self.authorizations.insert(
+                    tx.transaction_id,
+                    Authorization {
+                        currency: currency.clone(),
+                        amount,
+                        row: self.row,
+                        timestamp,
+                    },
+                );
+                tx.amount = Some(amount);
+                tx.currency = Some(currency);
+                tx.timestamp = timestamp;
+                self.transactions.insert(tx.transaction_id, tx);
+                // END CRITICAL SECTION
+            }
+            TransactionType::Capture => {
+                let authorization = self.get_authorization(&tx)?;
+                let amount = self.get_partial_amount(&tx, &authorization.currency, authorization.amount)?;
+                let currency = authorization.currency;
+                self.check_limits(timestamp, &currency, amount)?;
+
+                // Capturing part of the authorization releases the rest of the funds.
+                // TODO make this critical section atomic.
+                // BEGIN CRITICAL SECTION
+                let released = authorization.amount - amount;
+                let balance = self.get_balance_mut(&currency);
+                balance.reserved -= authorization.amount;
+                balance.available += released;
+                self.history.push(LedgerEntry {
+                    transaction_id: tx.transaction_id,
+                    r#type: TransactionType::Capture,
+                    kind: EntryKind::Transaction,
+                    currency: currency.clone(),
+                    available: released,
+                    held: Decimal::ZERO,
+                    reserved: -authorization.amount,
+                });
+                self.authorizations.remove(&tx.transaction_id);
+                if let Some(timestamp) = timestamp {
+                    self.debits.push_back(Debit {
+                        timestamp,
+                        currency,
+                        amount,
+                    });
+                }
+                // END CRITICAL SECTION
+            }
+            TransactionType::Interest => {
+                if self.transactions.contains_key(&tx.transaction_id) {
+                    return Err(TransactionError::DuplicateTransaction(tx.transaction_id));
+                }
+                let currency = self.get_currency(&tx);
+                let amount = self.get_amount(&tx, &currency)?;
+
+                // TODO make this critical section atomic.
+                // BEGIN CRITICAL SECTION
+                self.get_balance_mut(&currency).available += amount;
+                self.record(&tx, EntryKind::Transaction, &currency, amount, Decimal::ZERO);
+                tx.amount = Some(amount);
+                tx.currency = Some(currency);
+                tx.timestamp = timestamp;
+                self.transactions.insert(tx.transaction_id, tx);
+                // END CRITICAL SECTION
+            }
+            TransactionType::Custom(name) => {
+                // Custom transactions can only be processed with their handler.
+                return Err(TransactionError::UnknownTransactionType(name.clone()));
+            }
+            TransactionType::Void => {
+                let authorization = self.get_authorization(&tx)?;
+
+                // TODO make this critical section atomic.
+                // BEGIN CRITICAL SECTION
+                self.release(
+                    tx.transaction_id,
+                    TransactionType::Void,
+                    EntryKind::Transaction,
+                    authorization,
+                );
+                // END CRITICAL SECTION
+            }
+        };
+        self.last_timestamp = timestamp;
+        Ok(self.history[history_start..].to_vec())
+    }
+
+    /// Processes a transaction of a custom type with its handler, and returns
+    /// the movements of funds that it caused. Custom transactions have their
+    /// own transaction ID, like deposits and withdrawals.
+    pub fn process_custom_transaction(
+        &mut self,
+        mut tx: Transaction,
+        handler: &dyn TransactionHandler,
+    ) -> Result<Vec<LedgerEntry>, TransactionError> {
+        if self.locked {
+            return Err(TransactionError::AccountLocked);
+        }
+        let timestamp = self.get_timestamp(&tx)?;
+        if self.transactions.contains_key(&tx.transaction_id) {
+            return Err(TransactionError::DuplicateTransaction(tx.transaction_id));
+        }
+        let history_start = self.history.len();
+
+        let mut view = AccountView {
+            account: self,
+            tx: &tx,
+        };
+        if let Err(err) = handler.process(&tx, &mut view) {
+            self.rollback(history_start);
+            return Err(err);
+        }
+
+        let currency = self.get_currency(&tx);
+        tx.amount = tx
+            .amount
+            .map(|amount| self.config.get_precision(&currency).round(amount));
+        tx.currency = Some(currency);
+        tx.timestamp = timestamp;
+        self.transactions.insert(tx.transaction_id, tx);
+        self.last_timestamp = timestamp;
+        Ok(self.history[history_start..].to_vec())
+    }
+
+    // Undoes the movements of funds recorded since the start of the history.
+    fn rollback(&mut self, history_start: usize) {
+        let entries: Vec<LedgerEntry> = self.history.drain(history_start..).collect();
+        for entry in entries.iter().rev() {
+            let balance = self.get_balance_mut(&entry.currency);
+            balance.available -= entry.available;
+            balance.held -= entry.held;
+            balance.reserved -= entry.reserved;
+        }
+    }
+
+    /// Gets the time of a transaction, which defaults to the time of the
+    /// last transaction of the account. Timestamps can never go backwards.
+    fn get_timestamp(&self, tx: &Transaction) -> Result<Option<i64>, TransactionError> {
+        match (tx.timestamp, self.last_timestamp) {
+            (Some(timestamp), Some(last)) if timestamp < last => {
+                Err(TransactionError::TimestampOutOfOrder { timestamp, last })
+            }
+            (Some(timestamp), _) => Ok(Some(timestamp)),
+            (None, last) => Ok(last),
+        }
+    }
+
+    /// Checks that a withdrawal, a transfer or a capture does not go beyond the limits
+    /// of the account, before debiting it, and forgets the debits that
+    /// cannot count anymore.
+    fn check_limits(
+        &mut self,
+        timestamp: Option<i64>,
+        currency: &str,
+        amount: Decimal,
+    ) -> Result<(), TransactionError> {
+        let timestamp = match timestamp {
+            Some(timestamp) => timestamp,
+            None => return Ok(()),
+        };
+        let config = self.config.clone();
+        let limits = config.get_limits(self.client_id);
+        let day_start = timestamp.saturating_sub(timestamp.rem_euclid(DAY_SECONDS));
+        let window_start = timestamp.saturating_sub(limits.window_seconds);
+        while self
+            .debits
+            .front()
+            .is_some_and(|debit| debit.timestamp < day_start && debit.timestamp <= window_start)
+        {
+            self.debits.pop_front();
+        }
+
+        if let Some(limit) = limits.max_withdrawals {
+            let count = self
+                .debits
+                .iter()
+                .filter(|debit| debit.timestamp > window_start)
+                .count();
+            if count >= limit as usize {
+                return Err(TransactionError::WithdrawalCountExceeded {
+                    limit,
+                    window_seconds: limits.window_seconds,
+                });
+            }
+        }
+
+        if let Some(limit) = limits.daily_withdrawal_amount {
+            let withdrawn: Decimal = self
+                .debits
+                .iter()
+                .filter(|debit| debit.timestamp >= day_start && debit.currency == currency)
+                .map(|debit| debit.amount)
+                .sum();
+            if withdrawn + amount > limit {
+                return Err(TransactionError::DailyWithdrawalLimitExceeded {
+                    withdrawn,
+                    amount,
+                    limit,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Credits the destination account of a transfer that was processed
+    /// by the source account. This cannot fail, so the bank must check
+    /// that the account is not locked beforehand.
+    pub fn receive_transfer(&mut self, tx: &Transaction, currency: &str, amount: Decimal) -> Vec<LedgerEntry> {
+        self.get_balance_mut(currency).available += amount;
+        self.record(tx, EntryKind::Transaction, currency, amount, Decimal::ZERO);
+        self.history[self.history.len() - 1..].to_vec()
+    }
+
+    pub fn get_client_id(&self) -> u16 {
+        self.client_id
+    }
+
+    /// Gets the disputes whose funds are still held, ordered by transaction ID.
+    pub fn get_open_disputes(&self) -> Vec<OpenDispute> {
+        let mut disputes: Vec<OpenDispute> = self
+            .disputed_transactions
+            .iter()
+            .filter(|(_, dispute)| dispute.held > Decimal::ZERO)
+            .filter_map(|(transaction_id, dispute)| {
+                let tx = self.transactions.get(transaction_id)?;
+                Some(OpenDispute {
+                    client_id: self.client_id,
+                    transaction_id: *transaction_id,
+                    currency: self.get_currency(tx),
+                    held: dispute.held,
+                })
+            })
+            .collect();
+        disputes.sort_by_key(|dispute| dispute.transaction_id);
+        disputes
+    }
+
+    /// Gets the timestamp of the last transaction processed by the account, if any.
+    pub fn get_last_timestamp(&self) -> Option<i64> {
+        self.last_timestamp
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    /// Raises a flag on the account, for manual review.
+    pub fn raise_flag(&mut self, flag: &str) {
+        self.flags.insert(flag.to_string());
+    }
+
+    /// Gets the flags raised on the account, in alphabetical order.
+    pub fn get_flags(&self) -> Vec<&str> {
+        self.flags.iter().map(|flag| flag.as_str()).collect()
+    }
+
+    fn check_hold(&self) -> Result<(), TransactionError> {
+        match self.flags.contains(HOLD_FLAG) {
+            true => Err(TransactionError::AccountOnHold),
+            false => Ok(()),
+        }
+    }
+
+    fn is_disputable(&self, tx: &Transaction) -> bool {
+        match tx.get_type() {
+            TransactionType::Transfer => self.config.transfer_disputes == TransferDisputePolicy::Source,
+            _ => tx.is_disputable(),
+        }
+    }
+
+    /// Computes the fee of a deposit or a withdrawal, rounded to the
+    /// precision of its currency.
+    fn get_fee(&self, tx: &Transaction, currency: &str, amount: Decimal) -> Decimal {
+        match self.config.fees.get_fee(tx.get_type()) {
+            Some(fee) => self.config.get_precision(currency).round(fee.compute(amount)),
+            None => Decimal::ZERO,
+        }
+    }
+
+    fn charge_fee(&mut self, tx: &Transaction, currency: &str, fee: Decimal) {
+        if fee.is_zero() {
+            return;
+        }
+        self.debit(currency, fee);
+        self.record(tx, EntryKind::Fee, currency, -fee, Decimal::ZERO);
+        self.fees.insert(tx.transaction_id, fee);
+    }
+
+    /// Refunds the fee of a transaction, in proportion of the amount
+    /// that was charged back.
+    fn refund_fee(&mut self, tx: &Transaction, currency: &str, amount: Decimal) {
+        let fee = match self.fees.remove(&tx.transaction_id) {
+            Some(fee) => fee,
+            None => return,
+        };
+        let total = self.transactions[&tx.transaction_id].amount.unwrap();
+        let fee = match amount == total {
+            true => fee,
+            false => self.config.get_precision(currency).round(fee * amount / total),
+        };
+        self.get_balance_mut(currency).available += fee;
+        self.record(tx, EntryKind::FeeRefund, currency, fee, Decimal::ZERO);
+    }
+
+    /// Gets the amount of a deposit or a withdrawal, which cannot be negative.
+    /// The amount is rounded to the precision of its currency.
+    fn get_amount(&self, tx: &Transaction, currency: &str) -> Result<Decimal, TransactionError> {
+        match tx.amount {
+            None => Err(TransactionError::MissingAmount),
+            Some(amount) if amount < Decimal::ZERO => Err(TransactionError::InvalidAmount(amount)),
+            Some(amount) => self.config.get_precision(currency).validate(amount),
+        }
+    }
+
+    /// Gets the currency of a transaction, falling back to the default
+    /// currency for inputs that do not specify one.
+    fn get_currency(&self, tx: &Transaction) -> String {
+        match &tx.currency {
+            Some(currency) => currency.to_uppercase(),
+            None => self.config.default_currency.to_uppercase(),
+        }
+    }
+
+    fn get_balance_mut(&mut self, currency: &str) -> &mut Balance {
+        self.balances.entry(currency.to_string()).or_default()
+    }
+
+    /// Gets a transaction that was processed by the account.
+    pub fn get_transaction(&self, transaction_id: u32) -> Option<&Transaction> {
+        self.transactions.get(&transaction_id)
+    }
+
+    /// Gets the funds of the account, indexed by currency.
+    pub fn get_balances(&self) -> &BTreeMap<String, Balance> {
+        &self.balances
+    }
+
+    /// Gets the funds of the account in a currency.
+    pub fn get_balance(&self, currency: &str) -> Balance {
+        self.balances.get(currency).cloned().unwrap_or_default()
+    }
+
+    /// Checks that the available funds can be debited of the amount,
+    /// according to the balance policy of the account.
+    fn check_debit(&self, currency: &str, amount: Decimal) -> Result<(), TransactionError> {
+        let available = self.get_balance(currency).available;
+        let remaining = available - amount;
+        match self.config.get_balance_policy(self.client_id) {
+            BalancePolicy::Strict if remaining < Decimal::ZERO => {
+                Err(TransactionError::InsufficientFunds { available, amount })
+            }
+            BalancePolicy::Overdraft { limit } if remaining < -*limit => {
+                Err(TransactionError::OverdraftLimitExceeded {
+                    available,
+                    amount,
+                    limit: *limit,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn debit(&mut self, currency: &str, amount: Decimal) {
+        let balance = self.balances.entry(currency.to_string()).or_default();
+        balance.available -= amount;
+        if balance.available < Decimal::ZERO
+            && *self.config.get_balance_policy(self.client_id) == BalancePolicy::AllowAndFlag
+        {
+            self.flags.insert(OVERDRAWN_FLAG.to_string());
+        }
+    }
+
+    /// Gets the currency and the amount still held of the disputed
+    /// transaction referenced by a resolve or a chargeback.
+    fn get_disputed_amount(&self, tx: &Transaction) -> Result<(String, Decimal), TransactionError> {
+        let disputed_tx = match self.transactions.get(&tx.transaction_id) {
+            Some(tx) => tx,
+            None => return Err(TransactionError::UnknownTransaction(tx.transaction_id)),
+        };
+
+        let held = self.get_dispute(tx.transaction_id).held;
+        if held.is_zero() {
+            return Err(TransactionError::NotDisputed(tx.transaction_id));
+        }
+        Ok((self.get_currency(disputed_tx), held))
+    }
+
+    /// Gets the amount of a dispute, a resolve or a chargeback, which
+    /// defaults to the whole amount that can be disputed, resolved or
+    /// charged back.
+    fn get_partial_amount(
+        &self,
+        tx: &Transaction,
+        currency: &str,
+        maximum: Decimal,
+    ) -> Result<Decimal, TransactionError> {
+        let amount = match tx.amount {
+            None => return Ok(maximum),
+            Some(amount) if amount <= Decimal::ZERO => return Err(TransactionError::InvalidAmount(amount)),
+            Some(amount) => self.config.get_precision(currency).validate(amount)?,
+        };
+        if amount > maximum {
+            return Err(TransactionError::ExcessDisputeAmount {
+                amount,
+                remaining: maximum,
+            });
+        }
+        Ok(amount)
+    }
+
+    /// Gets the open authorization referenced by a capture or a void.
+    fn get_authorization(&self, tx: &Transaction) -> Result<Authorization, TransactionError> {
+        match self.transactions.get(&tx.transaction_id) {
+            Some(authorize_tx) if *authorize_tx.get_type() == TransactionType::Authorize => {}
+            _ => return Err(TransactionError::UnknownAuthorization(tx.transaction_id)),
+        };
+        match self.authorizations.get(&tx.transaction_id) {
+            Some(authorization) => Ok(authorization.clone()),
+            None => Err(TransactionError::AuthorizationClosed(tx.transaction_id)),
+        }
+    }
+
+    /// Releases the funds reserved by an authorization.
+    fn release(
+        &mut self,
+        transaction_id: u32,
+        r#type: TransactionType,
+        kind: EntryKind,
+        authorization: Authorization,
+    ) {
+        let balance = self.get_balance_mut(&authorization.currency);
+        balance.reserved -= authorization.amount;
+        balance.available += authorization.amount;
+        self.record_reserved(
+            transaction_id,
+            r#type,
+            kind,
+            &authorization.currency,
+            -authorization.amount,
+        );
+        self.authorizations.remove(&transaction_id);
+    }
+
+    /// Records the number of rows processed by the bank, and releases the
+    /// funds of the authorizations that expired, either by number of rows
+    /// or by time. The funds of a locked account are never released.
+    pub fn expire_authorizations(&mut self, row: u64, timestamp: Option<i64>) -> Vec<LedgerEntry> {
+        self.row = row;
+        let history_start = self.history.len();
+        if self.locked {
+            return vec![];
+        }
+        let expiry_rows = self.config.authorization_expiry_rows;
+        let expiry_seconds = self.config.authorization_expiry_seconds;
+        let is_expired = |authorization: &Authorization| {
+            let expired_rows = expiry_rows.is_some_and(|rows| row.saturating_sub(authorization.row) >= rows);
+            let expired_time = match (expiry_seconds, timestamp, authorization.timestamp) {
+                (Some(seconds), Some(now), Some(then)) => now.saturating_sub(then) >= seconds,
+                _ => false,
+            };
+            expired_rows || expired_time
+        };
+        let expired: Vec<(u32, Authorization)> = self
+            .authorizations
+            .iter()
+            .filter(|(_, authorization)| is_expired(authorization))
+            .map(|(id, authorization)| (*id, authorization.clone()))
+            .collect();
+        for (transaction_id, authorization) in expired {
+            self.release(
+                transaction_id,
+                TransactionType::Authorize,
+                EntryKind::Expiry,
+                authorization,
+            );
+        }
+        self.history[history_start..].to_vec()
+    }
+
+    /// Gets the amount reversed or refunded of a transaction.
+    pub fn get_reversed_amount(&self, transaction_id: u32) -> Decimal {
+        self.reversals.get(&transaction_id).cloned().unwrap_or_default()
+    }
+
+    /// Gets the amounts held and reversed of a transaction.
+    pub fn get_dispute(&self, transaction_id: u32) -> Dispute {
+        self.disputed_transactions
+            .get(&transaction_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn record(&mut self, tx: &Transaction, kind: EntryKind, currency: &str, available: Decimal, held: Decimal) {
+        self.history.push(LedgerEntry {
+            transaction_id: tx.transaction_id,
+            r#type: tx.get_type().clone(),
+            kind,
+            currency: currency.to_string(),
+            available,
+            held,
+            reserved: Decimal::ZERO,
+        });
+    }
+
+    /// Records funds moved from the available funds to the reserved
+    /// funds, or back when the amount is negative.
+    fn record_reserved(
+        &mut self,
+        transaction_id: u32,
+        r#type: TransactionType,
+        kind: EntryKind,
+        currency: &str,
+        amount: Decimal,
+    ) {
+        self.history.push(LedgerEntry {
+            transaction_id,
+            r#type,
+            kind,
+            currency: currency.to_string(),
+            available: -amount,
+            held: Decimal::ZERO,
+            reserved: amount,
+        });
+    }
+
+    /// Recomputes the state of the account from its history, and checks
+    /// that it matches the current state. The held funds must also match
+    /// the amounts held by the disputed transactions, in every currency.
+    pub fn audit(&self) -> Vec<AuditIssue> {
+        let mut issues: Vec<AuditIssue> = vec![];
+
+        let mut balances: BTreeMap<String, Balance> = BTreeMap::new();
+        let mut locked = false;
+        let mut disputes: HashMap<u32, Dispute> = HashMap::new();
+        for entry in &self.history {
+            let balance = balances.entry(entry.currency.clone()).or_default();
+            balance.available += entry.available;
+            balance.held += entry.held;
+            balance.reserved += entry.reserved;
+            if entry.kind != EntryKind::Transaction {
+                continue;
+            }
+            match entry.r#type {
+                TransactionType::Dispute | TransactionType::Resolve => {
+                    disputes.entry(entry.transaction_id).or_default().held += entry.held;
+                }
+                TransactionType::Chargeback => {
+                    let dispute = disputes.entry(entry.transaction_id).or_default();
+                    dispute.held += entry.held;
+                    dispute.reversed -= entry.held;
+                    locked = true;
                 }
+                _ => {}
+            };
+        }
+
+        let mut disputed: HashMap<String, Decimal> = HashMap::new();
+        let mut disputed_ids: Vec<&u32> = self.disputed_transactions.keys().collect();
+        disputed_ids.sort();
+        for transaction_id in disputed_ids {
+            let dispute = &self.disputed_transactions[transaction_id];
+            match self.transactions.get(transaction_id) {
+                Some(tx) if self.is_disputable(tx) && disputes.get(transaction_id) == Some(dispute) => {
+                    *disputed.entry(self.get_currency(tx)).or_default() += dispute.held;
+                }
+                _ => issues.push(AuditIssue::OrphanedDispute {
+                    client_id: self.client_id,
+                    transaction_id: *transaction_id,
+                }),
+            };
+        }
+
+        let mut authorized: HashMap<String, Decimal> = HashMap::new();
+        for authorization in self.authorizations.values() {
+            *authorized.entry(authorization.currency.clone()).or_default() += authorization.amount;
+        }
+
+        let mut currencies: BTreeSet<&String> = self.balances.keys().collect();
+        currencies.extend(balances.keys());
+        currencies.extend(disputed.keys());
+        currencies.extend(authorized.keys());
+        for currency in currencies {
+            let expected = balances.get(currency).cloned().unwrap_or_default();
+            let actual = self.get_balance(currency);
+            if expected.available != actual.available {
+                issues.push(AuditIssue::BalanceDrift {
+                    client_id: self.client_id,
+                    currency: currency.to_string(),
+                    balance: "available",
+                    expected: expected.available,
+                    actual: actual.available,
+                });
+            }
+            if expected.held != actual.held {
+                issues.push(AuditIssue::BalanceDrift {
+                    client_id: self.client_id,
+                    currency: currency.to_string(),
+                    balance: "held",
+                    expected: expected.held,
+                    actual: actual.held,
+                });
+            }
+            if expected.reserved != actual.reserved {
+                issues.push(AuditIssue::BalanceDrift {
+                    client_id: self.client_id,
+                    currency: currency.to_string(),
+                    balance: "reserved",
+                    expected: expected.reserved,
+                    actual: actual.reserved,
+                });
+            }
+            let authorized = authorized.get(currency).cloned().unwrap_or_default();
+            if authorized != actual.reserved {
+                issues.push(AuditIssue::ReservedMismatch {
+                    client_id: self.client_id,
+                    currency: currency.to_string(),
+                    reserved: actual.reserved,
+                    authorized,
+                });
+            }
+            if actual.held < Decimal::ZERO {
+                issues.push(AuditIssue::NegativeHeld {
+                    client_id: self.client_id,
+                    currency: currency.to_string(),
+                    held: actual.held,
+                });
+            }
+            let disputed = disputed.get(currency).cloned().unwrap_or_default();
+            if disputed != actual.held {
+                issues.push(AuditIssue::HeldMismatch {
+                    client_id: self.client_id,
+                    currency: currency.to_string(),
+                    held: actual.held,
+                    disputed,
+                });
+            }
+        }
+
+        if locked != self.locked {
+            issues.push(AuditIssue::LockDrift {
+                client_id: self.client_id,
+                expected: locked,
+            });
+        }
+
+        issues
+    }
+
+    /// Gets the state of the account in every currency it holds funds in,
+    /// or in the default currency when it holds none.
+    pub fn get_rows(&self) -> Vec<AccountRow> {
+        let default_currency = self.config.default_currency.to_uppercase();
+        let mut balances: Vec<(&String, &Balance)> = self.balances.iter().collect();
+        let empty_balance = Balance::default();
+        if balances.is_empty() {
+            balances.push((&default_currency, &empty_balance));
+        }
+        balances
+            .into_iter()
+            .map(|(currency, balance)| {
+                let precision = self.config.get_precision(currency);
+                AccountRow {
+                    client: self.client_id,
+                    currency: currency.clone(),
+                    available: precision.round(balance.available).normalize(),
+                    held: precision.round(balance.held).normalize(),
+                    reserved: precision.round(balance.reserved).normalize(),
+                    total: precision.round(balance.get_total()).normalize(),
+                    locked: self.locked,
+                    flags: self.get_flags().join("|"),
+                }
+            })
+            .collect()
+    }
+
+    pub fn print(&self) {
+        for row in self.get_rows() {
+            println!(
+                "{}, {}, {}, {}, {}, {}, {}, {}",
+                row.client,
+                row.currency,
+                row.available,
+                row.held,
+                row.reserved,
+                row.total,
+                row.locked,
+                row.flags
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Fee, Limits, Precision, Rounding};
+
+    impl Account {
+        // Gets the funds of the account in the default currency.
+        fn balance(&mut self) -> &mut Balance {
+            let currency = self.config.default_currency.clone();
+            self.get_balance_mut(&currency)
+        }
+    }
+
+    #[test]
+    pub fn test_deposit() {
+        let mut account = Account::new(1);
+        let tx = Transaction {
+            client_id: 1,
+            transaction_id: 1,
+            r#type: TransactionType::Deposit,
+            amount: Some(Decimal::from_str("100.0").unwrap()),
+            currency: None,
+            destination: None,
+            timestamp: None,
+        };
+        account.process_transaction(tx).unwrap();
+        assert_eq!(account.balance().available, Decimal::from_str("100.0").unwrap());
+    }
+
+    #[test]
+    pub fn test_duplicate_deposit() {
+        let mut account = Account::new(1);
+        let tx = Transaction {
+            client_id: 1,
+            transaction_id: 1,
+            r#type: TransactionType::Deposit,
+            amount: Some(Decimal::from_str("100.0").unwrap()),
+            currency: None,
+            destination: None,
+            timestamp: None,
+        };
+        account.process_transaction(tx.clone()).unwrap();
+        // We will ignore a transaction that was already processed.
+        assert_eq!(
+            account.process_transaction(tx),
+            Err(TransactionError::DuplicateTransaction(1))
+        );
+        assert_eq!(account.balance().available, Decimal::from_str("100.0").unwrap());
+    }
+
+    #[test]
+    pub fn test_withdraw() {
+        let mut account = Account::new(1);
+        account.balance().available = Decimal::from_str("100.0").unwrap();
+
+        let tx = Transaction {
+            client_id: 1,
+            transaction_id: 1,
+            r#type: TransactionType::Withdrawal,
+            amount: Some(Decimal::from_str("50.0").unwrap()),
+            currency: None,
+            destination: None,
+            timestamp: None,
+        };
+        account.process_transaction(tx).unwrap();
+        assert_eq!(account.balance().available, Decimal::from_str("50.0").unwrap());
+    }
+
+    #[test]
+    pub fn test_duplicate_withdraw() {
+        let mut account = Account::new(1);
+        account.balance().available = Decimal::from_str("100.0").unwrap();
+
+        let tx = Transaction {
+            client_id: 1,
+            transaction_id: 1,
+            r#type: TransactionType::Withdrawal,
+            amount: Some(Decimal::from_str("50.0").unwrap()),
+            currency: None,
+            destination: None,
+            timestamp: None,
+        };
+        account.process_transaction(tx.clone()).unwrap();
+        // We will ignore a transaction that was already processed.
+        assert_eq!(
+            account.process_transaction(tx),
+            Err(TransactionError::DuplicateTransaction(1))
+        );
+        assert_eq!(account.balance().available, Decimal::from_str("50.0").unwrap());
+    }
+
+    #[test]
+    pub fn test_withdraw_insufficient_funds() {
+        let mut account = Account::new(1);
+        account.balance().available = Decimal::from_str("100.0").unwrap();
+
+        let tx = Transaction {
+            client_id: 1,
+            transaction_id: 1,
+            r#type: TransactionType::Withdrawal,
+            amount: Some(Decimal::from_str("150.0").unwrap()),
+            currency: None,
+            destination: None,
+            timestamp: None,
+        };
+        assert_eq!(
+            account.process_transaction(tx),
+            Err(TransactionError::InsufficientFunds {
+                available: Decimal::from_str("100.0").unwrap(),
+                amount: Decimal::from_str("150.0").unwrap(),
+            })
+        );
+        assert_eq!(account.balance().available, Decimal::from_str("100.0").unwrap());
+    }
+
+    #[test]
+    pub fn test_empty_account() {
+        let mut account = Account::new(1);
+        account.balance().available = Decimal::from_str("100.0").unwrap();
+
+        let tx = Transaction {
+            client_id: 1,
+            transaction_id: 1,
+            r#type: TransactionType::Withdrawal,
+            amount: Some(Decimal::from_str("100.0").unwrap()),
+            currency: None,
+            destination: None,
+            timestamp: None,
+        };
+        account.process_transaction(tx).unwrap();
+        assert_eq!(account.balance().available, Decimal::from_str("0.0").unwrap());
+    }
+
+    #[test]
+    pub fn test_dispute() {
+        let mut account = Account::new(1);
+
+        let tx = Transaction {
+            client_id: 1,
+            transaction_id: 1,
+            r#type: TransactionType::Deposit,
+            amount: Some(Decimal::from_str("150.0").unwrap()),
+            currency: None,
+            destination: None,
+            timestamp: None,
+        };
+        let dispute_tx = Transaction {
+            client_id: 1,
+            transaction_id: 1,
+            r#type: TransactionType::Dispute,
+            amount: None,
+            currency: None,
+            destination: None,
+            timestamp: None,
+        };
+        account.process_transaction(tx).unwrap();
+        account.process_transaction(dispute_tx).unwrap();
+        assert_eq!(account.balance().available, Decimal::from_str("0.0").unwrap());
+        assert_eq!(account.balance().held, Decimal::from_str("150.0").unwrap());
+        assert_eq!(account.balance().get_total(), Decimal::from_str("150.0").unwrap());
+    }
+
+    #[test]
+    pub fn test_duplicate_dispute() {
+        let mut account = Account::new(1);
+
+        let tx = Transaction {
+            client_id: 1,
+            transaction_id: 1,
+            r#type: TransactionType::Deposit,
+            amount: Some(Decimal::from_str("150.0").unwrap()),
+            currency: None,
+            destination: None,
+            timestamp: None,
+        };
+        let dispute_tx = Transaction {
+            client_id: 1,
+            transaction_id: 1,
+            r#type: TransactionType::Dispute,
+            amount: None,
+            currency: None,
+            destination: None,
+            timestamp: None,
+        };
+        account.process_transaction(tx).unwrap();
+        account.process_transaction(dispute_tx.clone()).unwrap();
+        assert_eq!(
+            account.process_transaction(dispute_tx),
+            Err(TransactionError::AlreadyDisputed(1))
+        );
+        assert_eq!(account.balance().available, Decimal::from_str("0.0").unwrap());
+        assert_eq!(account.balance().held, Decimal::from_str("150.0").unwrap());
+        assert_eq!(account.balance().get_total(), Decimal::from_str("150.0").unwrap());
+    }
+
+    #[test]
+    pub fn test_dispute_invalid_transaction() {
+        let mut account = Account::new(1);
+
+        let tx = Transaction {
+            client_id: 1,
+            transaction_id: 1,
+            r#type: TransactionType::Deposit,
+            amount: Some(Decimal::from_str("150.0").unwrap()),
+            currency: None,
+            destination: None,
+            timestamp: None,
+        };
+        let dispute_tx = Transaction {
+            client_id: 1,
+            transaction_id: 2,
+            r#type: TransactionType::Dispute,
+            amount: None,
+            currency: None,
+            destination: None,
+            timestamp: None,
+        };
+        account.process_transaction(tx).unwrap();
+        assert_eq!(
+            account.process_transaction(dispute_tx),
+            Err(TransactionError::UnknownTransaction(2))
+        );
+        assert_eq!(account.balance().available, Decimal::from_str("150.0").unwrap());
+        assert_eq!(account.balance().held, Decimal::from_str("0.0").unwrap());
+        assert_eq!(account.balance().get_total(), Decimal::from_str("150.0").unwrap());
+    }
+
+    #[test]
+    pub fn test_resolve() {
+        let mut account = Account::new(1);
+
+        let tx = Transaction {
+            client_id: 1,
+            transaction_id: 1,
+            r#type: TransactionType::Deposit,
+            amount: Some(Decimal::from_str("150.0").unwrap()),
+            currency: None,
+            destination: None,
+            timestamp: None,
+        };
+        let dispute_tx = Transaction {
+            client_id: 1,
+            transaction_id: 1,
+            r#type: TransactionType::Dispute,
+            amount: Some(Decimal::from_str("150.0").unwrap()),
+            currency: None,
+            destination: None,
+            timestamp: None,
+        };
+        let resolve_tx = Transaction {
+            client_id: 1,
+            transaction_id: 1,
+            r#type: TransactionType::Resolve,
+            amount: None,
+            currency: None,
+            destination: None,
+            timestamp: None,
+        };
+        account.process_transaction(tx).unwrap();
+        account.process_transaction(dispute_tx).unwrap();
+        account.process_transaction(resolve_tx).unwrap();
+        assert_eq!(account.balance().available, Decimal::from_str("150.0").unwrap());
+        assert_eq!(account.balance().held, Decimal::from_str("0.0").unwrap());
+        assert_eq!(account.balance().get_total(), Decimal::from_str("150.0").unwrap());
+    }
+
+    #[test]
+    pub fn test_resolve_invalid_transaction() {
+        let mut account = Account::new(1);
+        account.balance().available = Decimal::from_str("100.0").unwrap();
+
+        let resolve_tx = Transaction {
+            client_id: 1,
+            transaction_id: 7,
+            r#type: TransactionType::Resolve,
+            amount: None,
+            currency: None,
+            destination: None,
+            timestamp: None,
+        };
+        assert_eq!(
+            account.process_transaction(resolve_tx),
+            Err(TransactionError::UnknownTransaction(7))
+        );
+        assert_eq!(account.balance().available, Decimal::from_str("100.0").unwrap());
+        assert_eq!(account.balance().held, Decimal::from_str("0.0").unwrap());
+    }
+
+    #[test]
+    pub fn test_chargeback() {
+        let mut account = Account::new(1);
+
+        let tx = Transaction {
+            client_id: 1,
+            transaction_id: 1,
+            r#type: TransactionType::Deposit,
+            amount: Some(Decimal::from_str("150.0").unwrap()),
+            currency: None,
+            destination: None,
+            timestamp: None,
+        };
+        let dispute_tx = Transaction {
+            client_id: 1,
+            transaction_id: 1,
+            r#type: TransactionType::Dispute,
+            amount: None,
+            currency: None,
+            destination: None,
+            timestamp: None,
+        };
+        let chargeback_tx = Transaction {
+            client_id: 1,
+            transaction_id: 1,
+            r#type: TransactionType::Chargeback,
+            amount: None,
+            currency: None,
+            destination: None,
+            timestamp: None,
+        };
+        account.process_transaction(tx).unwrap();
+        account.process_transaction(dispute_tx).unwrap();
+        account.process_transaction(chargeback_tx).unwrap();
+        assert_eq!(account.balance().available, Decimal::from_str("0.0").unwrap());
+        assert_eq!(account.balance().held, Decimal::from_str("0.0").unwrap());
+        assert!(account.locked);
+    }
+
+    #[test]
+    pub fn test_chargeback_invalid_transaction() {
+        let mut account = Account::new(1);
+        account.balance().available = Decimal::from_str("100.0").unwrap();
+
+        let chargeback_tx = Transaction {
+            client_id: 1,
+            transaction_id: 1,
+            r#type: TransactionType::Chargeback,
+            amount: None,
+            currency: None,
+            destination: None,
+            timestamp: None,
+        };
+        assert_eq!(
+            account.process_transaction(chargeback_tx),
+            Err(TransactionError::UnknownTransaction(1))
+        );
+        assert_eq!(account.balance().available, Decimal::from_str("100.0").unwrap());
+        assert_eq!(account.balance().held, Decimal::from_str("0.0").unwrap());
+        assert!(!account.locked);
+    }
+
+    // Builds a transaction of the client 1, with the ID 1.
+    fn new_transaction(r#type: TransactionType, amount: Option<&str>) -> Transaction {
+        Transaction {
+            client_id: 1,
+            transaction_id: 1,
+            r#type,
+            amount: amount.map(|a| Decimal::from_str(a).unwrap()),
+            currency: None,
+            destination: None,
+            timestamp: None,
+        }
+    }
+
+    #[test]
+    pub fn test_partial_dispute() {
+        let mut account = Account::new(1);
+        account
+            .process_transaction(new_transaction(TransactionType::Deposit, Some("150.0")))
+            .unwrap();
+
+        account
+            .process_transaction(new_transaction(TransactionType::Dispute, Some("100.0")))
+            .unwrap();
+        assert_eq!(account.balance().available, Decimal::from_str("50.0").unwrap());
+        assert_eq!(account.balance().held, Decimal::from_str("100.0").unwrap());
+
+        assert_eq!(
+            account.process_transaction(new_transaction(TransactionType::Dispute, Some("60.0"))),
+            Err(TransactionError::ExcessDisputeAmount {
+                amount: Decimal::from_str("60.0").unwrap(),
+                remaining: Decimal::from_str("50.0").unwrap(),
+            })
+        );
+        account
+            .process_transaction(new_transaction(TransactionType::Dispute, None))
+            .unwrap();
+        assert_eq!(account.balance().held, Decimal::from_str("150.0").unwrap());
+        assert_eq!(
+            account.process_transaction(new_transaction(TransactionType::Dispute, None)),
+            Err(TransactionError::AlreadyDisputed(1))
+        );
+
+        account
+            .process_transaction(new_transaction(TransactionType::Resolve, Some("30.0")))
+            .unwrap();
+        assert_eq!(account.balance().available, Decimal::from_str("30.0").unwrap());
+        assert_eq!(account.balance().held, Decimal::from_str("120.0").unwrap());
+        assert_eq!(account.audit(), vec![]);
+    }
+
+    #[test]
+    pub fn test_partial_chargeback() {
+        let mut account = Account::new(1);
+        account
+            .process_transaction(new_transaction(TransactionType::Deposit, Some("150.0")))
+            .unwrap();
+        account
+            .process_transaction(new_transaction(TransactionType::Dispute, None))
+            .unwrap();
+        assert_eq!(
+            account.process_transaction(new_transaction(TransactionType::Chargeback, Some("200.0"))),
+            Err(TransactionError::ExcessDisputeAmount {
+                amount: Decimal::from_str("200.0").unwrap(),
+                remaining: Decimal::from_str("150.0").unwrap(),
+            })
+        );
+        account
+            .process_transaction(new_transaction(TransactionType::Chargeback, Some("100.0")))
+            .unwrap();
+
+        assert_eq!(account.balance().available, Decimal::from_str("0.0").unwrap());
+        assert_eq!(account.balance().held, Decimal::from_str("50.0").unwrap());
+        assert_eq!(
+            account.get_dispute(1),
+            Dispute {
+                held: Decimal::from_str("50.0").unwrap(),
+                reversed: Decimal::from_str("100.0").unwrap(),
+            }
+        );
+        assert!(account.locked);
+        assert_eq!(account.audit(), vec![]);
+    }
+
+    #[test]
+    pub fn test_reversal() {
+        let mut account = Account::new(1);
+        account
+            .process_transaction(new_transaction(TransactionType::Deposit, Some("150.0")))
+            .unwrap();
+        account
+            .process_transaction(new_transaction(TransactionType::Reversal, Some("100.0")))
+            .unwrap();
+        assert_eq!(account.balance().available, Decimal::from_str("50.0").unwrap());
+        assert_eq!(
+            account.process_transaction(new_transaction(TransactionType::Reversal, None)),
+            Err(TransactionError::AlreadyReversed(1))
+        );
+
+        // Only the amount that was not reversed can be disputed.
+        account
+            .process_transaction(new_transaction(TransactionType::Dispute, None))
+            .unwrap();
+        assert_eq!(account.balance().available, Decimal::from_str("0.0").unwrap());
+        assert_eq!(account.balance().held, Decimal::from_str("50.0").unwrap());
+        assert!(!account.locked);
+        assert_eq!(account.audit(), vec![]);
+    }
+
+    #[test]
+    pub fn test_reversal_under_dispute() {
+        let mut account = Account::new(1);
+        account
+            .process_transaction(new_transaction(TransactionType::Deposit, Some("150.0")))
+            .unwrap();
+        account
+            .process_transaction(new_transaction(TransactionType::Dispute, Some("10.0")))
+            .unwrap();
+        assert_eq!(
+            account.process_transaction(new_transaction(TransactionType::Reversal, None)),
+            Err(TransactionError::UnderDispute(1))
+        );
+        assert_eq!(account.balance().available, Decimal::from_str("140.0").unwrap());
+    }
 
-                if self.disputed_transactions.contains(&tx.transaction_id) {
-                    eprintln!("Transaction {} is already disputed.", disputed_tx.transaction_id);
-                    return;
-                }
+    #[test]
+    pub fn test_refund() {
+        let mut account = Account::new(1);
+        account.balance().available = Decimal::from_str("100.0").unwrap();
+        account
+            .process_transaction(new_transaction(TransactionType::Withdrawal, Some("80.0")))
+            .unwrap();
+        account
+            .process_transaction(new_transaction(TransactionType::Refund, Some("30.0")))
+            .unwrap();
+        assert_eq!(account.balance().available, Decimal::from_str("50.0").unwrap());
 
-                // TODO make this critical section atomic.
-                // BEGIN CRITICAL SECTION
-                self.available -= disputed_tx.amount.unwrap();
-                self.held += disputed_tx.amount.unwrap();
-                self.disputed_transactions.insert(tx.transaction_id.clone());
-                // END CRITICAL SECTION
-            }
-            TransactionType::Resolve => {
-                let disputed_tx = match self.transactions.get(&tx.transaction_id) {
-                    Some(tx) => tx,
-                    None => return,
-                };
+        let mut account = Account::new(1);
+        account
+            .process_transaction(new_transaction(TransactionType::Deposit, Some("80.0")))
+            .unwrap();
+        assert_eq!(
+            account.process_transaction(new_transaction(TransactionType::Refund, None)),
+            Err(TransactionError::NotReversible(1))
+        );
+    }
 
-                if !self.disputed_transactions.contains(&tx.transaction_id) {
-                    return;
-                }
+    #[test]
+    pub fn test_authorize_and_capture() {
+        let mut account = Account::new(1);
+        account.balance().available = Decimal::from_str("100.0").unwrap();
+        account
+            .process_transaction(new_transaction(TransactionType::Authorize, Some("80.0")))
+            .unwrap();
+        assert_eq!(account.balance().available, Decimal::from_str("20.0").unwrap());
+        assert_eq!(account.balance().reserved, Decimal::from_str("80.0").unwrap());
+        assert_eq!(account.balance().get_total(), Decimal::from_str("100.0").unwrap());
 
-                // TODO make this critical section atomic.
-                // BEGIN CRITICAL SECTION
-                self.available += disputed_tx.amount.unwrap();
-                self.held -= disputed_tx.amount.unwrap();
-                self.disputed_transactions.remove(&tx.transaction_id);
-                // END CRITICAL SECTION
-            }
-            TransactionType::Chargeback => {
-                let disputed_tx = match self.transactions.get(&tx.transaction_id) {
-                    Some(tx) => tx,
-                    None => return,
-                };
+        // Capturing part of the authorization releases the rest of the funds.
+        account
+            .process_transaction(new_transaction(TransactionType::Capture, Some("50.0")))
+            .unwrap();
+        assert_eq!(account.balance().available, Decimal::from_str("50.0").unwrap());
+        assert_eq!(account.balance().reserved, Decimal::from_str("0.0").unwrap());
+        assert_eq!(
+            account.process_transaction(new_transaction(TransactionType::Void, None)),
+            Err(TransactionError::AuthorizationClosed(1))
+        );
+    }
 
-                if !self.disputed_transactions.contains(&tx.transaction_id) {
-                    return;
-                }
+    #[test]
+    pub fn test_authorize_and_void() {
+        let mut account = Account::new(1);
+        account
+            .process_transaction(new_transaction(TransactionType::Deposit, Some("100.0")))
+            .unwrap();
+        assert_eq!(
+            account.process_transaction(new_transaction(TransactionType::Void, None)),
+            Err(TransactionError::UnknownAuthorization(1))
+        );
 
-                // TODO make this critical section atomic.
-                // BEGIN CRITICAL SECTION
-                self.held -= disputed_tx.amount.unwrap();
-                self.disputed_transactions.remove(&tx.transaction_id);
-                self.locked = true;
-                // END CRITICAL SECTION
-            }
+        let authorize_tx = Transaction {
+            transaction_id: 2,
+            ..new_transaction(TransactionType::Authorize, Some("80.0"))
+        };
+        let void_tx = Transaction {
+            transaction_id: 2,
+            ..new_transaction(TransactionType::Void, None)
         };
+        account.process_transaction(authorize_tx).unwrap();
+        account.process_transaction(void_tx).unwrap();
+        assert_eq!(account.balance().available, Decimal::from_str("100.0").unwrap());
+        assert_eq!(account.balance().reserved, Decimal::from_str("0.0").unwrap());
+        assert_eq!(account.audit(), vec![]);
     }
 
-    pub fn get_total(&self) -> Decimal {
-        self.available + self.held
-    }
+    #[test]
+    pub fn test_authorization_expiry() {
+        let config = Config {
+            authorization_expiry_rows: Some(3),
+            ..Config::default()
+        };
+        let mut account = Account::with_config(1, Rc::new(config));
+        account.expire_authorizations(1, None);
+        account
+            .process_transaction(new_transaction(TransactionType::Deposit, Some("100.0")))
+            .unwrap();
+        account.expire_authorizations(2, None);
+        let authorize_tx = Transaction {
+            transaction_id: 2,
+            ..new_transaction(TransactionType::Authorize, Some("80.0"))
+        };
+        account.process_transaction(authorize_tx).unwrap();
 
-    pub fn print(&self) {
-        println!(
-            "{}, {}, {}, {}, {}",
-            self.client_id,
-            self.available
-                .round_dp(crate::consts::DECIMAL_PRECISION)
-                .normalize(),
-            self.held.round_dp(crate::consts::DECIMAL_PRECISION).normalize(),
-            self.get_total()
-                .round_dp(crate::consts::DECIMAL_PRECISION)
-                .normalize(),
-            self.locked
+        assert_eq!(account.expire_authorizations(4, None), vec![]);
+        let entries = account.expire_authorizations(5, None);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].kind, EntryKind::Expiry);
+        assert_eq!(account.balance().available, Decimal::from_str("100.0").unwrap());
+        assert_eq!(account.balance().reserved, Decimal::from_str("0.0").unwrap());
+
+        let capture_tx = Transaction {
+            transaction_id: 2,
+            ..new_transaction(TransactionType::Capture, None)
+        };
+        assert_eq!(
+            account.process_transaction(capture_tx),
+            Err(TransactionError::AuthorizationClosed(2))
         );
+        assert_eq!(account.audit(), vec![]);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    fn timestamped_transaction(
+        r#type: TransactionType,
+        transaction_id: u32,
+        amount: Option<&str>,
+        timestamp: i64,
+    ) -> Transaction {
+        Transaction {
+            transaction_id,
+            timestamp: Some(timestamp),
+            ..new_transaction(r#type, amount)
+        }
+    }
 
     #[test]
-    pub fn test_deposit() {
+    pub fn test_timestamp_out_of_order() {
         let mut account = Account::new(1);
-        let tx = Transaction {
-            client_id: 1,
-            transaction_id: 1,
-            r#type: TransactionType::Deposit,
-            amount: Some(Decimal::from_str("100.0").unwrap()),
+        account
+            .process_transaction(timestamped_transaction(
+                TransactionType::Deposit,
+                1,
+                Some("100.0"),
+                1000,
+            ))
+            .unwrap();
+        assert_eq!(
+            account.process_transaction(timestamped_transaction(
+                TransactionType::Withdrawal,
+                2,
+                Some("10.0"),
+                999
+            )),
+            Err(TransactionError::TimestampOutOfOrder {
+                timestamp: 999,
+                last: 1000
+            })
+        );
+
+        // Transactions without a timestamp happen at the time of the last one.
+        account
+            .process_transaction(Transaction {
+                transaction_id: 3,
+                ..new_transaction(TransactionType::Withdrawal, Some("10.0"))
+            })
+            .unwrap();
+        assert_eq!(account.transactions[&3].timestamp, Some(1000));
+        account
+            .process_transaction(timestamped_transaction(
+                TransactionType::Withdrawal,
+                4,
+                Some("10.0"),
+                1000,
+            ))
+            .unwrap();
+        assert_eq!(account.balance().available, Decimal::from_str("80.0").unwrap());
+    }
+
+    #[test]
+    pub fn test_dispute_window() {
+        let config = Config {
+            dispute_window_seconds: Some(120 * DAY_SECONDS),
+            ..Config::default()
         };
-        account.process_transaction(tx);
-        assert_eq!(account.available, Decimal::from_str("100.0").unwrap());
+        let mut account = Account::with_config(1, Rc::new(config));
+        account
+            .process_transaction(timestamped_transaction(
+                TransactionType::Deposit,
+                1,
+                Some("100.0"),
+                0,
+            ))
+            .unwrap();
+        account
+            .process_transaction(timestamped_transaction(
+                TransactionType::Deposit,
+                2,
+                Some("100.0"),
+                DAY_SECONDS,
+            ))
+            .unwrap();
+
+        let now = 121 * DAY_SECONDS;
+        assert_eq!(
+            account.process_transaction(timestamped_transaction(TransactionType::Dispute, 1, None, now)),
+            Err(TransactionError::DisputeWindowExpired(1))
+        );
+        account
+            .process_transaction(timestamped_transaction(TransactionType::Dispute, 2, None, now))
+            .unwrap();
+        assert_eq!(account.balance().held, Decimal::from_str("100.0").unwrap());
     }
 
     #[test]
-    pub fn test_duplicate_deposit() {
-        let mut account = Account::new(1);
-        let tx = Transaction {
-            client_id: 1,
-            transaction_id: 1,
-            r#type: TransactionType::Deposit,
-            amount: Some(Decimal::from_str("100.0").unwrap()),
+    pub fn test_extreme_timestamps() {
+        let mut config = Config {
+            dispute_window_seconds: Some(120 * DAY_SECONDS),
+            ..Config::default()
         };
-        account.process_transaction(tx.clone());
-        // We will ignore a transaction that was already processed.
-        account.process_transaction(tx);
-        assert_eq!(account.available, Decimal::from_str("100.0").unwrap());
+        config.limits.daily_withdrawal_amount = Some(Decimal::from_str("100.0").unwrap());
+        let mut account = Account::with_config(1, Rc::new(config));
+        account
+            .process_transaction(timestamped_transaction(
+                TransactionType::Deposit,
+                1,
+                Some("100.0"),
+                i64::MIN,
+            ))
+            .unwrap();
+        account
+            .process_transaction(timestamped_transaction(
+                TransactionType::Withdrawal,
+                2,
+                Some("10.0"),
+                i64::MIN,
+            ))
+            .unwrap();
+        account
+            .process_transaction(timestamped_transaction(
+                TransactionType::Withdrawal,
+                3,
+                Some("10.0"),
+                i64::MAX,
+            ))
+            .unwrap();
+        assert_eq!(
+            account.process_transaction(timestamped_transaction(
+                TransactionType::Dispute,
+                1,
+                None,
+                i64::MAX
+            )),
+            Err(TransactionError::DisputeWindowExpired(1))
+        );
     }
 
     #[test]
-    pub fn test_withdraw() {
-        let mut account = Account::new(1);
-        account.available = Decimal::from_str("100.0").unwrap();
+    pub fn test_daily_withdrawal_limit() {
+        let mut config = Config::default();
+        config.limits.daily_withdrawal_amount = Some(Decimal::from_str("100.0").unwrap());
+        let mut account = Account::with_config(1, Rc::new(config));
+        account
+            .process_transaction(timestamped_transaction(
+                TransactionType::Deposit,
+                1,
+                Some("500.0"),
+                0,
+            ))
+            .unwrap();
+        account
+            .process_transaction(timestamped_transaction(
+                TransactionType::Withdrawal,
+                2,
+                Some("60.0"),
+                3600,
+            ))
+            .unwrap();
+        assert_eq!(
+            account.process_transaction(timestamped_transaction(
+                TransactionType::Withdrawal,
+                3,
+                Some("50.0"),
+                7200
+            )),
+            Err(TransactionError::DailyWithdrawalLimitExceeded {
+                withdrawn: Decimal::from_str("60.0").unwrap(),
+                amount: Decimal::from_str("50.0").unwrap(),
+                limit: Decimal::from_str("100.0").unwrap(),
+            })
+        );
 
-        let tx = Transaction {
-            client_id: 1,
-            transaction_id: 1,
-            r#type: TransactionType::Withdrawal,
-            amount: Some(Decimal::from_str("50.0").unwrap()),
-        };
-        account.process_transaction(tx);
-        assert_eq!(account.available, Decimal::from_str("50.0").unwrap());
+        // The limit is reset at midnight.
+        account
+            .process_transaction(timestamped_transaction(
+                TransactionType::Withdrawal,
+                4,
+                Some("100.0"),
+                DAY_SECONDS,
+            ))
+            .unwrap();
+        assert_eq!(account.balance().available, Decimal::from_str("340.0").unwrap());
     }
 
     #[test]
-    pub fn test_duplicate_withdraw() {
-        let mut account = Account::new(1);
-        account.available = Decimal::from_str("100.0").unwrap();
+    pub fn test_capture_limits() {
+        let mut config = Config::default();
+        config.limits.daily_withdrawal_amount = Some(Decimal::from_str("100.0").unwrap());
+        let mut account = Account::with_config(1, Rc::new(config));
+        account
+            .process_transaction(timestamped_transaction(
+                TransactionType::Deposit,
+                1,
+                Some("500.0"),
+                0,
+            ))
+            .unwrap();
+        account
+            .process_transaction(timestamped_transaction(
+                TransactionType::Withdrawal,
+                2,
+                Some("60.0"),
+                3600,
+            ))
+            .unwrap();
+        account
+            .process_transaction(timestamped_transaction(
+                TransactionType::Authorize,
+                3,
+                Some("50.0"),
+                3600,
+            ))
+            .unwrap();
+        assert_eq!(
+            account.process_transaction(timestamped_transaction(TransactionType::Capture, 3, None, 7200)),
+            Err(TransactionError::DailyWithdrawalLimitExceeded {
+                withdrawn: Decimal::from_str("60.0").unwrap(),
+                amount: Decimal::from_str("50.0").unwrap(),
+                limit: Decimal::from_str("100.0").unwrap(),
+            })
+        );
+        account
+            .process_transaction(timestamped_transaction(
+                TransactionType::Capture,
+                3,
+                Some("40.0"),
+                7200,
+            ))
+            .unwrap();
 
-        let tx = Transaction {
-            client_id: 1,
-            transaction_id: 1,
-            r#type: TransactionType::Withdrawal,
-            amount: Some(Decimal::from_str("50.0").unwrap()),
+        // The capture counts against the limit of the day.
+        assert_eq!(
+            account.process_transaction(timestamped_transaction(
+                TransactionType::Withdrawal,
+                4,
+                Some("1.0"),
+                7200
+            )),
+            Err(TransactionError::DailyWithdrawalLimitExceeded {
+                withdrawn: Decimal::from_str("100.0").unwrap(),
+                amount: Decimal::from_str("1.0").unwrap(),
+                limit: Decimal::from_str("100.0").unwrap(),
+            })
+        );
+        assert_eq!(account.balance().available, Decimal::from_str("400.0").unwrap());
+    }
+
+    #[test]
+    pub fn test_withdrawal_count_limit() {
+        let mut config = Config::default();
+        config.limits.max_withdrawals = Some(2);
+        config.limits.window_seconds = 3600;
+        config.client_limits.insert(2, Limits::default());
+        let config = Rc::new(config);
+
+        let mut account = Account::with_config(1, config.clone());
+        account
+            .process_transaction(timestamped_transaction(
+                TransactionType::Deposit,
+                1,
+                Some("500.0"),
+                0,
+            ))
+            .unwrap();
+        account
+            .process_transaction(timestamped_transaction(
+                TransactionType::Withdrawal,
+                2,
+                Some("10.0"),
+                0,
+            ))
+            .unwrap();
+        account
+            .process_transaction(timestamped_transaction(
+                TransactionType::Withdrawal,
+                3,
+                Some("10.0"),
+                1800,
+            ))
+            .unwrap();
+        assert_eq!(
+            account.process_transaction(timestamped_transaction(
+                TransactionType::Withdrawal,
+                4,
+                Some("10.0"),
+                3599
+            )),
+            Err(TransactionError::WithdrawalCountExceeded {
+                limit: 2,
+                window_seconds: 3600
+            })
+        );
+        // The first withdrawal is out of the window.
+        account
+            .process_transaction(timestamped_transaction(
+                TransactionType::Withdrawal,
+                5,
+                Some("10.0"),
+                3600,
+            ))
+            .unwrap();
+        assert_eq!(account.balance().available, Decimal::from_str("470.0").unwrap());
+
+        // Client 2 overrides the limits.
+        let mut account = Account::with_config(2, config);
+        account
+            .process_transaction(timestamped_transaction(
+                TransactionType::Deposit,
+                1,
+                Some("500.0"),
+                0,
+            ))
+            .unwrap();
+        for transaction_id in 2..6 {
+            account
+                .process_transaction(timestamped_transaction(
+                    TransactionType::Withdrawal,
+                    transaction_id,
+                    Some("10.0"),
+                    0,
+                ))
+                .unwrap();
+        }
+    }
+
+    #[test]
+    pub fn test_authorization_expiry_by_time() {
+        let config = Config {
+            authorization_expiry_seconds: Some(DAY_SECONDS),
+            ..Config::default()
         };
-        account.process_transaction(tx.clone());
-        // We will ignore a transaction that was already processed.
-        account.process_transaction(tx);
-        assert_eq!(account.available, Decimal::from_str("50.0").unwrap());
+        let mut account = Account::with_config(1, Rc::new(config));
+        account
+            .process_transaction(timestamped_transaction(
+                TransactionType::Deposit,
+                1,
+                Some("100.0"),
+                0,
+            ))
+            .unwrap();
+        account
+            .process_transaction(timestamped_transaction(
+                TransactionType::Authorize,
+                2,
+                Some("80.0"),
+                3600,
+            ))
+            .unwrap();
+
+        assert_eq!(account.expire_authorizations(3, Some(DAY_SECONDS)), vec![]);
+        let entries = account.expire_authorizations(4, Some(DAY_SECONDS + 3600));
+        assert_eq!(entries.len(), 1);
+        assert_eq!(account.balance().available, Decimal::from_str("100.0").unwrap());
+        assert_eq!(account.audit(), vec![]);
     }
 
     #[test]
-    pub fn test_withdraw_insufficient_funds() {
+    pub fn test_hold() {
         let mut account = Account::new(1);
-        account.available = Decimal::from_str("100.0").unwrap();
+        account
+            .process_transaction(new_transaction(TransactionType::Deposit, Some("100.0")))
+            .unwrap();
+        account.raise_flag(HOLD_FLAG);
 
-        let tx = Transaction {
-            client_id: 1,
-            transaction_id: 1,
-            r#type: TransactionType::Withdrawal,
-            amount: Some(Decimal::from_str("150.0").unwrap()),
+        let withdrawal_tx = Transaction {
+            transaction_id: 2,
+            ..new_transaction(TransactionType::Withdrawal, Some("10.0"))
+        };
+        assert_eq!(
+            account.process_transaction(withdrawal_tx),
+            Err(TransactionError::AccountOnHold)
+        );
+        // Only the debits are rejected.
+        let deposit_tx = Transaction {
+            transaction_id: 3,
+            ..new_transaction(TransactionType::Deposit, Some("10.0"))
         };
-        account.process_transaction(tx);
-        assert_eq!(account.available, Decimal::from_str("100.0").unwrap());
+        account.process_transaction(deposit_tx).unwrap();
+        assert_eq!(account.balance().available, Decimal::from_str("110.0").unwrap());
+    }
+
+    // Credits the amount of the transaction, then debits twice that amount.
+    struct CreditThenDebit;
+    impl TransactionHandler for CreditThenDebit {
+        fn process(&self, _tx: &Transaction, account: &mut AccountView) -> Result<(), TransactionError> {
+            let (currency, amount) = (account.get_currency(), account.get_amount()?);
+            account.credit(&currency, amount)?;
+            account.debit(&currency, amount * Decimal::TWO)
+        }
     }
 
     #[test]
-    pub fn test_empty_account() {
+    pub fn test_custom_transaction() {
         let mut account = Account::new(1);
-        account.available = Decimal::from_str("100.0").unwrap();
+        let tx = new_transaction(TransactionType::Custom("adjustment".to_string()), Some("10.0"));
+        assert_eq!(
+            account.process_transaction(tx.clone()),
+            Err(TransactionError::UnknownTransactionType("adjustment".to_string()))
+        );
 
-        let tx = Transaction {
-            client_id: 1,
-            transaction_id: 1,
-            r#type: TransactionType::Withdrawal,
-            amount: Some(Decimal::from_str("100.0").unwrap()),
-        };
-        account.process_transaction(tx);
-        assert_eq!(account.available, Decimal::from_str("0.0").unwrap());
+        // A failed handler does not move any funds.
+        assert_eq!(
+            account.process_custom_transaction(tx.clone(), &CreditThenDebit),
+            Err(TransactionError::InsufficientFunds {
+                available: Decimal::from_str("10.0").unwrap(),
+                amount: Decimal::from_str("20.0").unwrap(),
+            })
+        );
+        assert_eq!(account.balance().available, Decimal::ZERO);
+        assert_eq!(account.audit(), vec![]);
+
+        account.balance().available = Decimal::from_str("100.0").unwrap();
+        let entries = account
+            .process_custom_transaction(tx.clone(), &CreditThenDebit)
+            .unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(account.balance().available, Decimal::from_str("90.0").unwrap());
+        assert_eq!(
+            account.process_custom_transaction(tx, &CreditThenDebit),
+            Err(TransactionError::DuplicateTransaction(1))
+        );
     }
 
     #[test]
-    pub fn test_dispute() {
+    pub fn test_multi_currency() {
         let mut account = Account::new(1);
 
-        let tx = Transaction {
+        let usd_tx = Transaction {
             client_id: 1,
             transaction_id: 1,
             r#type: TransactionType::Deposit,
-            amount: Some(Decimal::from_str("150.0").unwrap()),
+            amount: Some(Decimal::from_str("100.0").unwrap()),
+            currency: None,
+            destination: None,
+            timestamp: None,
+        };
+        let eur_tx = Transaction {
+            client_id: 1,
+            transaction_id: 2,
+            r#type: TransactionType::Deposit,
+            amount: Some(Decimal::from_str("50.0").unwrap()),
+            currency: Some("eur".to_string()),
+            destination: None,
+            timestamp: None,
         };
+        let withdrawal_tx = Transaction {
+            client_id: 1,
+            transaction_id: 3,
+            r#type: TransactionType::Withdrawal,
+            amount: Some(Decimal::from_str("60.0").unwrap()),
+            currency: Some("EUR".to_string()),
+            destination: None,
+            timestamp: None,
+        };
+        // The dispute applies in the currency of the disputed transaction,
+        // whatever the currency of the dispute itself.
         let dispute_tx = Transaction {
             client_id: 1,
-            transaction_id: 1,
+            transaction_id: 2,
             r#type: TransactionType::Dispute,
             amount: None,
+            currency: Some("USD".to_string()),
+            destination: None,
+            timestamp: None,
         };
-        account.process_transaction(tx);
-        account.process_transaction(dispute_tx);
-        assert_eq!(account.available, Decimal::from_str("0.0").unwrap());
-        assert_eq!(account.held, Decimal::from_str("150.0").unwrap());
-        assert_eq!(account.get_total(), Decimal::from_str("150.0").unwrap());
+        account.process_transaction(usd_tx).unwrap();
+        account.process_transaction(eur_tx).unwrap();
+        assert_eq!(
+            account.process_transaction(withdrawal_tx),
+            Err(TransactionError::InsufficientFunds {
+                available: Decimal::from_str("50.0").unwrap(),
+                amount: Decimal::from_str("60.0").unwrap(),
+            })
+        );
+        account.process_transaction(dispute_tx).unwrap();
+
+        let eur = account.get_balance("EUR");
+        assert_eq!(eur.available, Decimal::from_str("0.0").unwrap());
+        assert_eq!(eur.held, Decimal::from_str("50.0").unwrap());
+        assert_eq!(account.balance().available, Decimal::from_str("100.0").unwrap());
+        assert_eq!(account.balance().held, Decimal::from_str("0.0").unwrap());
+        assert_eq!(account.audit(), vec![]);
     }
 
     #[test]
-    pub fn test_duplicate_dispute() {
-        let mut account = Account::new(1);
+    pub fn test_deposit_precision() {
+        let mut config = Config::default();
+        config.currency_precisions.insert(
+            "JPY".to_string(),
+            Precision {
+                decimals: 0,
+                rounding: Rounding::Reject,
+            },
+        );
+        config.precision.rounding = Rounding::HalfUp;
+        let mut account = Account::with_config(1, Rc::new(config));
 
         let tx = Transaction {
             client_id: 1,
             transaction_id: 1,
             r#type: TransactionType::Deposit,
-            amount: Some(Decimal::from_str("150.0").unwrap()),
+            amount: Some(Decimal::from_str("1.00005").unwrap()),
+            currency: None,
+            destination: None,
+            timestamp: None,
         };
-        let dispute_tx = Transaction {
+        let jpy_tx = Transaction {
             client_id: 1,
-            transaction_id: 1,
-            r#type: TransactionType::Dispute,
-            amount: None,
+            transaction_id: 2,
+            r#type: TransactionType::Deposit,
+            amount: Some(Decimal::from_str("100.5").unwrap()),
+            currency: Some("JPY".to_string()),
+            destination: None,
+            timestamp: None,
         };
-        account.process_transaction(tx);
-        account.process_transaction(dispute_tx.clone());
-        account.process_transaction(dispute_tx);
-        assert_eq!(account.available, Decimal::from_str("0.0").unwrap());
-        assert_eq!(account.held, Decimal::from_str("150.0").unwrap());
-        assert_eq!(account.get_total(), Decimal::from_str("150.0").unwrap());
+        account.process_transaction(tx).unwrap();
+        assert_eq!(
+            account.process_transaction(jpy_tx),
+            Err(TransactionError::ExcessPrecision {
+                amount: Decimal::from_str("100.5").unwrap(),
+                decimals: 0,
+            })
+        );
+        assert_eq!(account.balance().available, Decimal::from_str("1.0001").unwrap());
+        assert_eq!(account.get_balance("JPY"), Balance::default());
     }
 
     #[test]
-    pub fn test_dispute_invalid_transaction() {
-        let mut account = Account::new(1);
+    pub fn test_deposit_fee() {
+        let mut config = Config::default();
+        config.fees.deposit = Some(Fee::Flat {
+            amount: Decimal::from_str("2.0").unwrap(),
+        });
+        let mut account = Account::with_config(1, Rc::new(config));
 
         let tx = Transaction {
             client_id: 1,
             transaction_id: 1,
             r#type: TransactionType::Deposit,
-            amount: Some(Decimal::from_str("150.0").unwrap()),
+            amount: Some(Decimal::from_str("100.0").unwrap()),
+            currency: None,
+            destination: None,
+            timestamp: None,
         };
-        let dispute_tx = Transaction {
+        let entries = account.process_transaction(tx).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].kind, EntryKind::Fee);
+        assert_eq!(entries[1].available, Decimal::from_str("-2.0").unwrap());
+        assert_eq!(account.balance().available, Decimal::from_str("98.0").unwrap());
+        assert_eq!(account.audit(), vec![]);
+    }
+
+    #[test]
+    pub fn test_withdraw_fee_insufficient_funds() {
+        let mut config = Config::default();
+        config.fees.withdrawal = Some(Fee::Percentage {
+            percent: Decimal::from_str("1.0").unwrap(),
+        });
+        let mut account = Account::with_config(1, Rc::new(config));
+        account.balance().available = Decimal::from_str("100.0").unwrap();
+
+        let tx = Transaction {
             client_id: 1,
-            transaction_id: 2,
-            r#type: TransactionType::Dispute,
-            amount: None,
+            transaction_id: 1,
+            r#type: TransactionType::Withdrawal,
+            amount: Some(Decimal::from_str("100.0").unwrap()),
+            currency: None,
+            destination: None,
+            timestamp: None,
         };
-        account.process_transaction(tx);
-        account.process_transaction(dispute_tx);
-        assert_eq!(account.available, Decimal::from_str("150.0").unwrap());
-        assert_eq!(account.held, Decimal::from_str("0.0").unwrap());
-        assert_eq!(account.get_total(), Decimal::from_str("150.0").unwrap());
+        assert_eq!(
+            account.process_transaction(tx),
+            Err(TransactionError::InsufficientFunds {
+                available: Decimal::from_str("100.0").unwrap(),
+                amount: Decimal::from_str("101.0").unwrap(),
+            })
+        );
+        assert_eq!(account.balance().available, Decimal::from_str("100.0").unwrap());
     }
 
     #[test]
-    pub fn test_resolve() {
-        let mut account = Account::new(1);
+    pub fn test_chargeback_fee_refund() {
+        let mut config = Config::default();
+        config.fees.deposit = Some(Fee::Flat {
+            amount: Decimal::from_str("2.0").unwrap(),
+        });
+        config.fees.chargeback = FeeRefundPolicy::Refund;
+        let mut account = Account::with_config(1, Rc::new(config));
 
         let tx = Transaction {
             client_id: 1,
             transaction_id: 1,
             r#type: TransactionType::Deposit,
-            amount: Some(Decimal::from_str("150.0").unwrap()),
+            amount: Some(Decimal::from_str("100.0").unwrap()),
+            currency: None,
+            destination: None,
+            timestamp: None,
         };
         let dispute_tx = Transaction {
             client_id: 1,
             transaction_id: 1,
             r#type: TransactionType::Dispute,
-            amount: Some(Decimal::from_str("150.0").unwrap()),
+            amount: None,
+            currency: None,
+            destination: None,
+            timestamp: None,
         };
-        let resolve_tx = Transaction {
+        let chargeback_tx = Transaction {
             client_id: 1,
             transaction_id: 1,
-            r#type: TransactionType::Resolve,
+            r#type: TransactionType::Chargeback,
             amount: None,
+            currency: None,
+            destination: None,
+            timestamp: None,
         };
-        account.process_transaction(tx);
-        account.process_transaction(dispute_tx);
-        account.process_transaction(resolve_tx);
-        assert_eq!(account.available, Decimal::from_str("150.0").unwrap());
-        assert_eq!(account.held, Decimal::from_str("0.0").unwrap());
-        assert_eq!(account.get_total(), Decimal::from_str("150.0").unwrap());
-    }
-
-    #[test]
-    pub fn test_resolve_invalid_transaction() {
-        let mut account = Account::new(1);
-        account.available = Decimal::from_str("100.0").unwrap();
-
-        let resolve_tx = Transaction {
+        let other_tx = Transaction {
             client_id: 1,
-            transaction_id: 7,
-            r#type: TransactionType::Resolve,
-            amount: None,
+            transaction_id: 2,
+            r#type: TransactionType::Deposit,
+            amount: Some(Decimal::from_str("10.0").unwrap()),
+            currency: None,
+            destination: None,
+            timestamp: None,
         };
-        account.process_transaction(resolve_tx);
-        assert_eq!(account.available, Decimal::from_str("100.0").unwrap());
-        assert_eq!(account.held, Decimal::from_str("0.0").unwrap());
+        account.process_transaction(tx).unwrap();
+        account.process_transaction(other_tx).unwrap();
+        account.process_transaction(dispute_tx).unwrap();
+        let entries = account.process_transaction(chargeback_tx).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].kind, EntryKind::FeeRefund);
+        assert_eq!(account.balance().available, Decimal::from_str("8.0").unwrap());
+        assert_eq!(account.balance().held, Decimal::from_str("0.0").unwrap());
     }
 
-    #[test]
-    pub fn test_chargeback() {
-        let mut account = Account::new(1);
-
-        let tx = Transaction {
+    fn deposit_withdraw_and_dispute(account: &mut Account) -> Result<Vec<LedgerEntry>, TransactionError> {
+        let deposit_tx = Transaction {
             client_id: 1,
             transaction_id: 1,
             r#type: TransactionType::Deposit,
-            amount: Some(Decimal::from_str("150.0").unwrap()),
+            amount: Some(Decimal::from_str("100.0").unwrap()),
+            currency: None,
+            destination: None,
+            timestamp: None,
+        };
+        let withdrawal_tx = Transaction {
+            client_id: 1,
+            transaction_id: 2,
+            r#type: TransactionType::Withdrawal,
+            amount: Some(Decimal::from_str("80.0").unwrap()),
+            currency: None,
+            destination: None,
+            timestamp: None,
         };
         let dispute_tx = Transaction {
             client_id: 1,
             transaction_id: 1,
             r#type: TransactionType::Dispute,
             amount: None,
+            currency: None,
+            destination: None,
+            timestamp: None,
         };
-        let chargeback_tx = Transaction {
-            client_id: 1,
-            transaction_id: 1,
-            r#type: TransactionType::Chargeback,
-            amount: None,
+        account.process_transaction(deposit_tx).unwrap();
+        account.process_transaction(withdrawal_tx).unwrap();
+        account.process_transaction(dispute_tx)
+    }
+
+    fn account_with_policy(policy: BalancePolicy) -> Account {
+        let config = Config {
+            balance_policy: policy,
+            ..Config::default()
         };
-        account.process_transaction(tx);
-        account.process_transaction(dispute_tx);
-        account.process_transaction(chargeback_tx);
-        assert_eq!(account.available, Decimal::from_str("0.0").unwrap());
-        assert_eq!(account.held, Decimal::from_str("0.0").unwrap());
-        assert_eq!(account.locked, true);
+        Account::with_config(1, Rc::new(config))
     }
 
     #[test]
-    pub fn test_chargeback_invalid_transaction() {
-        let mut account = Account::new(1);
-        account.available = Decimal::from_str("100.0").unwrap();
+    pub fn test_dispute_after_withdrawal_strict() {
+        let mut account = account_with_policy(BalancePolicy::Strict);
+        assert_eq!(
+            deposit_withdraw_and_dispute(&mut account),
+            Err(TransactionError::InsufficientFunds {
+                available: Decimal::from_str("20.0").unwrap(),
+                amount: Decimal::from_str("100.0").unwrap(),
+            })
+        );
+        assert_eq!(account.balance().available, Decimal::from_str("20.0").unwrap());
+        assert_eq!(account.balance().held, Decimal::from_str("0.0").unwrap());
+    }
 
-        let chargeback_tx = Transaction {
+    #[test]
+    pub fn test_dispute_after_withdrawal_overdraft() {
+        let mut account = account_with_policy(BalancePolicy::Overdraft {
+            limit: Decimal::from_str("80.0").unwrap(),
+        });
+        deposit_withdraw_and_dispute(&mut account).unwrap();
+        assert_eq!(account.balance().available, Decimal::from_str("-80.0").unwrap());
+        assert_eq!(account.balance().held, Decimal::from_str("100.0").unwrap());
+        assert!(account.flags.is_empty());
+    }
+
+    #[test]
+    pub fn test_dispute_after_withdrawal_overdraft_exceeded() {
+        let mut account = account_with_policy(BalancePolicy::Overdraft {
+            limit: Decimal::from_str("50.0").unwrap(),
+        });
+        assert_eq!(
+            deposit_withdraw_and_dispute(&mut account),
+            Err(TransactionError::OverdraftLimitExceeded {
+                available: Decimal::from_str("20.0").unwrap(),
+                amount: Decimal::from_str("100.0").unwrap(),
+                limit: Decimal::from_str("50.0").unwrap(),
+            })
+        );
+        assert_eq!(account.balance().available, Decimal::from_str("20.0").unwrap());
+    }
+
+    #[test]
+    pub fn test_dispute_after_withdrawal_allow_and_flag() {
+        let mut account = account_with_policy(BalancePolicy::AllowAndFlag);
+        deposit_withdraw_and_dispute(&mut account).unwrap();
+        assert_eq!(account.balance().available, Decimal::from_str("-80.0").unwrap());
+        assert_eq!(account.balance().held, Decimal::from_str("100.0").unwrap());
+        assert!(account.flags.contains(OVERDRAWN_FLAG));
+        assert_eq!(account.audit(), vec![]);
+    }
+
+    #[test]
+    pub fn test_withdraw_overdraft() {
+        let mut account = account_with_policy(BalancePolicy::Overdraft {
+            limit: Decimal::from_str("50.0").unwrap(),
+        });
+        let tx = Transaction {
             client_id: 1,
             transaction_id: 1,
-            r#type: TransactionType::Chargeback,
-            amount: None,
+            r#type: TransactionType::Withdrawal,
+            amount: Some(Decimal::from_str("50.0").unwrap()),
+            currency: None,
+            destination: None,
+            timestamp: None,
         };
-        account.process_transaction(chargeback_tx);
-        assert_eq!(account.available, Decimal::from_str("100.0").unwrap());
-        assert_eq!(account.held, Decimal::from_str("0.0").unwrap());
-        assert_eq!(account.locked, false);
+        account.process_transaction(tx).unwrap();
+        assert_eq!(account.balance().available, Decimal::from_str("-50.0").unwrap());
+    }
+
+    #[test]
+    pub fn test_audit() {
+        let mut account = Account::new(1);
+        let txs = vec![
+            (TransactionType::Deposit, 1, Some("150.0")),
+            (TransactionType::Deposit, 2, Some("50.0")),
+            (TransactionType::Withdrawal, 3, Some("25.0")),
+            (TransactionType::Dispute, 2, None),
+            (TransactionType::Resolve, 2, None),
+            (TransactionType::Dispute, 1, None),
+        ];
+        for (r#type, transaction_id, amount) in txs {
+            account
+                .process_transaction(Transaction {
+                    client_id: 1,
+                    transaction_id,
+                    r#type,
+                    amount: amount.map(|a| Decimal::from_str(a).unwrap()),
+                    currency: None,
+                    destination: None,
+                    timestamp: None,
+                })
+                .unwrap();
+        }
+        assert_eq!(account.balance().held, Decimal::from_str("150.0").unwrap());
+        assert_eq!(account.audit(), vec![]);
+    }
+
+    #[test]
+    pub fn test_audit_balance_drift() {
+        let mut account = Account::new(1);
+        account.balance().available = Decimal::from_str("100.0").unwrap();
+
+        assert_eq!(
+            account.audit(),
+            vec![AuditIssue::BalanceDrift {
+                client_id: 1,
+                currency: "USD".to_string(),
+                balance: "available",
+                expected: Decimal::ZERO,
+                actual: Decimal::from_str("100.0").unwrap(),
+            }]
+        );
+    }
+
+    #[test]
+    pub fn test_audit_orphaned_dispute() {
+        let mut account = Account::new(1);
+        account.disputed_transactions.insert(42, Dispute::default());
+
+        assert_eq!(
+            account.audit(),
+            vec![AuditIssue::OrphanedDispute {
+                client_id: 1,
+                transaction_id: 42,
+            }]
+        );
     }
 
     #[test]
@@ -415,10 +2550,16 @@ mod tests {
                 transaction_id: i,
                 r#type: TransactionType::Deposit,
                 amount: Some(Decimal::from_str("0.1").unwrap()),
+                currency: None,
+                destination: None,
+                timestamp: None,
             };
-            account.process_transaction(chargeback_tx);
+            account.process_transaction(chargeback_tx).unwrap();
         }
 
-        assert_eq!(account.available, Decimal::from_str("100000.0").unwrap());
+        assert_eq!(
+            account.balance().available,
+            Decimal::from_str("100000.0").unwrap()
+        );
     }
 }
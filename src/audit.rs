@@ -0,0 +1,99 @@
+use rust_decimal::prelude::*;
+use std::fmt;
+
+/// An inconsistency found when auditing an account.
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum AuditIssue {
+    /// The current balance differs from the one recomputed from the history.
+    BalanceDrift {
+        client_id: u16,
+        currency: String,
+        balance: &'static str,
+        expected: Decimal,
+        actual: Decimal,
+    },
+    /// The lock state differs from the one recomputed from the history.
+    LockDrift { client_id: u16, expected: bool },
+    NegativeHeld {
+        client_id: u16,
+        currency: String,
+        held: Decimal,
+    },
+    /// The held funds do not match the amounts of the disputed transactions.
+    HeldMismatch {
+        client_id: u16,
+        currency: String,
+        held: Decimal,
+        disputed: Decimal,
+    },
+    /// The reserved funds do not match the amounts of the open authorizations.
+    ReservedMismatch {
+        client_id: u16,
+        currency: String,
+        reserved: Decimal,
+        authorized: Decimal,
+    },
+    /// A disputed transaction ID that does not reference a disputable transaction.
+    OrphanedDispute { client_id: u16, transaction_id: u32 },
+}
+impl fmt::Display for AuditIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AuditIssue::BalanceDrift {
+                client_id,
+                currency,
+                balance,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Client {}: {} {} balance is {} but the history gives {}.",
+                client_id, currency, balance, actual, expected
+            ),
+            AuditIssue::LockDrift { client_id, expected } => write!(
+                f,
+                "Client {}: account should be {}.",
+                client_id,
+                if *expected { "locked" } else { "unlocked" }
+            ),
+            AuditIssue::NegativeHeld {
+                client_id,
+                currency,
+                held,
+            } => write!(
+                f,
+                "Client {}: {} held balance {} is negative.",
+                client_id, currency, held
+            ),
+            AuditIssue::HeldMismatch {
+                client_id,
+                currency,
+                held,
+                disputed,
+            } => write!(
+                f,
+                "Client {}: {} held balance is {} but the disputed transactions total {}.",
+                client_id, currency, held, disputed
+            ),
+            AuditIssue::ReservedMismatch {
+                client_id,
+                currency,
+                reserved,
+                authorized,
+            } => write!(
+                f,
+                "Client {}: {} reserved balance is {} but the open authorizations total {}.",
+                client_id, currency, reserved, authorized
+            ),
+            AuditIssue::OrphanedDispute {
+                client_id,
+                transaction_id,
+            } => write!(
+                f,
+                "Client {}: disputed transaction {} does not exist or is not disputable.",
+                client_id, transaction_id
+            ),
+        }
+    }
+}
@@ -1,53 +1,1190 @@
+use rust_decimal::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::rc::Rc;
 
-use crate::account::Account;
-use crate::transaction::Transaction;
+use crate::account::{Account, OpenDispute, HOLD_FLAG};
+use crate::audit::AuditIssue;
+use crate::config::Config;
+use crate::consts::SYNTHETIC_TRANSACTION_ID_START;
+use crate::error::TransactionError;
+use crate::fraud::FraudEngine;
+use crate::handler::TransactionHandler;
+use crate::ledger::{EntryKind, LedgerEntry};
+use crate::log::{Destination, Logger};
+use crate::observer::{Event, Observer};
+use crate::snapshot::AccountRow;
+use crate::transaction::{Transaction, TransactionType};
 
 pub struct Bank {
     /// The accounts handled by the bank, indexed
     /// by customer ID.
     pub accounts: HashMap<u16, Account>,
+
+    /// The revenue account of the bank, holding the fees
+    /// charged to the customers, indexed by currency.
+    pub revenue: BTreeMap<String, Decimal>,
+
+    /// The number of transactions rejected by the limits of
+    /// the accounts, indexed by customer ID.
+    pub limit_violations: BTreeMap<u16, u64>,
+
+    /// The handlers of the custom transaction types, indexed by type name.
+    handlers: HashMap<String, Rc<dyn TransactionHandler>>,
+
+    /// The observers notified of every event of the bank.
+    observers: Vec<Box<dyn Observer>>,
+
+    /// The fraud rules watching the transactions of every customer.
+    fraud: FraudEngine,
+
+    /// The number of transactions processed by the bank.
+    rows: u64,
+
+    /// The number of malformed rows skipped by the bank.
+    malformed_rows: u64,
+
+    logger: Logger,
+
+    /// The line of the input holding the transaction being processed,
+    /// when it was read from a file.
+    line: Option<u64>,
+
+    /// The ID of the next transaction generated by the bank.
+    next_synthetic_id: u32,
+
+    /// The latest timestamp of the transactions processed by the bank.
+    timestamp: Option<i64>,
+
+    config: Rc<Config>,
+}
+/// The state of a bank saved between runs. The configuration, the handlers,
+/// the observers and the state of the fraud rules are not saved.
+#[derive(Serialize)]
+#[derive(Deserialize)]
+struct State<A> {
+    accounts: Vec<A>,
+
+    revenue: BTreeMap<String, Decimal>,
+
+    limit_violations: BTreeMap<u16, u64>,
+
+    rows: u64,
+
+    next_synthetic_id: u32,
+
+    timestamp: Option<i64>,
+}
+
+/// A point of the input at which the processing stops.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub enum AsOf {
+    /// After the row at this line of the file, the header being line 1.
+    Line(u64),
+    /// After the first row with this transaction ID.
+    Transaction(u32),
+}
+impl AsOf {
+    /// Parses a point of the input, written `line:<number>` or `tx:<id>`.
+    pub fn from_string(as_of: &str) -> Result<AsOf, String> {
+        let parsed = match as_of.split_once(':') {
+            Some(("line", line)) => line.parse().ok().map(AsOf::Line),
+            Some(("tx", transaction_id)) => transaction_id.parse().ok().map(AsOf::Transaction),
+            _ => None,
+        };
+        parsed.ok_or(format!(
+            "Invalid point of the input {}, expected line:<number> or tx:<id>.",
+            as_of
+        ))
+    }
+}
+impl fmt::Display for AsOf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AsOf::Line(line) => write!(f, "line {}", line),
+            AsOf::Transaction(transaction_id) => write!(f, "transaction {}", transaction_id),
+        }
+    }
+}
+
+impl Default for Bank {
+    fn default() -> Bank {
+        Bank::new()
+    }
 }
 impl Bank {
     pub fn new() -> Bank {
+        Bank::with_config(Config::default())
+    }
+
+    pub fn with_config(config: Config) -> Bank {
         Bank {
             accounts: HashMap::new(),
+            revenue: BTreeMap::new(),
+            limit_violations: BTreeMap::new(),
+            handlers: HashMap::new(),
+            observers: vec![],
+            fraud: FraudEngine::with_rules(&config.fraud),
+            logger: Logger::with_config(&config.log),
+            line: None,
+            rows: 0,
+            malformed_rows: 0,
+            next_synthetic_id: SYNTHETIC_TRANSACTION_ID_START,
+            timestamp: None,
+            config: Rc::new(config),
         }
     }
 
+    pub fn get_config(&self) -> &Config {
+        &self.config
+    }
+
     pub fn process_transactions(&mut self, transactions_file_path: &str) -> Result<(), Box<dyn Error>> {
+        self.read_transactions(transactions_file_path, None)?;
+        Ok(())
+    }
+
+    /// Processes the transactions of a file up to a point, leaving the
+    /// accounts as they stood at that point. Returns whether the point was
+    /// reached before the end of the file.
+    pub fn process_transactions_as_of(
+        &mut self,
+        transactions_file_path: &str,
+        as_of: &AsOf,
+    ) -> Result<bool, Box<dyn Error>> {
+        self.read_transactions(transactions_file_path, Some(as_of))
+    }
+
+    fn read_transactions(
+        &mut self,
+        transactions_file_path: &str,
+        as_of: Option<&AsOf>,
+    ) -> Result<bool, Box<dyn Error>> {
+        let mut reached = false;
         let mut reader = csv::ReaderBuilder::new()
             .trim(csv::Trim::All)
             .has_headers(true)
             .from_path(transactions_file_path)?;
-        for result in reader.deserialize() {
-            let tx: Transaction = result.expect("Could not deserialize transaction.");
-            self.process_transaction(tx);
+        let headers = reader.headers()?.clone();
+        let mut record = csv::StringRecord::new();
+        loop {
+            // Malformed rows stop the processing, unless they are skipped. An
+            // unreadable file always stops the processing.
+            match reader.read_record(&mut record) {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(err) if matches!(err.kind(), csv::ErrorKind::Io(_)) => return Err(Box::new(err)),
+                Err(err) => {
+                    let line = err.position().map(|position| position.line());
+                    if let (Some(AsOf::Line(last)), Some(line)) = (as_of, line) {
+                        if line > *last {
+                            reached = true;
+                            break;
+                        }
+                    }
+                    self.handle_malformed_row(line, err)?;
+                    continue;
+                }
+            };
+            self.line = record.position().map(|position| position.line());
+            if let (Some(AsOf::Line(last)), Some(line)) = (as_of, self.line) {
+                if line > *last {
+                    reached = true;
+                    break;
+                }
+            }
+            match record.deserialize::<Transaction>(Some(&headers)) {
+                Ok(tx) => {
+                    let transaction_id = tx.transaction_id;
+                    self.process_transaction(tx);
+                    if as_of == Some(&AsOf::Transaction(transaction_id)) {
+                        reached = true;
+                        break;
+                    }
+                }
+                Err(err) => self.handle_malformed_row(self.line, err)?,
+            };
+            if as_of == Some(&AsOf::Line(self.line.unwrap_or_default())) {
+                reached = true;
+                break;
+            }
+        }
+        self.line = None;
+        self.expire_authorizations();
+        Ok(reached)
+    }
+
+    // Logs a malformed row, and fails unless malformed rows are skipped.
+    fn handle_malformed_row(&mut self, line: Option<u64>, err: csv::Error) -> Result<(), Box<dyn Error>> {
+        self.logger.error(
+            "Could not read transaction.",
+            &[
+                ("line", Value::from(line)),
+                ("error", Value::from(err.to_string())),
+            ],
+        );
+        self.emit(Event::Malformed {
+            line,
+            error: err.to_string(),
+        });
+        if !self.config.skip_malformed_rows {
+            self.line = None;
+            return Err(Box::new(err));
         }
+        self.malformed_rows += 1;
         Ok(())
     }
 
+    /// Gets the number of malformed rows that were skipped.
+    pub fn get_malformed_rows(&self) -> u64 {
+        self.malformed_rows
+    }
+
+    /// Registers the handler of a custom transaction type. Type names are case
+    /// insensitive. Fails if the name is the name of a built-in transaction
+    /// type, or cannot be read from the input.
+    pub fn register_handler(
+        &mut self,
+        name: &str,
+        handler: impl TransactionHandler + 'static,
+    ) -> Result<(), String> {
+        match TransactionType::from_string(name)? {
+            TransactionType::Custom(name) => {
+                self.handlers.insert(name, Rc::new(handler));
+                Ok(())
+            }
+            _ => Err(format!("The transaction type {} is built in.", name)),
+        }
+    }
+
+    /// Adds an observer, notified of every event of the bank.
+    pub fn add_observer(&mut self, observer: impl Observer + 'static) {
+        self.observers.push(Box::new(observer));
+    }
+
+    /// Saves the accounts and the counters of the bank to a JSON file. The file
+    /// is replaced atomically, so that a failed save keeps the previous state.
+    pub fn save_state(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        let mut client_ids: Vec<&u16> = self.accounts.keys().collect();
+        client_ids.sort();
+        let state = State {
+            accounts: client_ids
+                .into_iter()
+                .map(|client_id| &self.accounts[client_id])
+                .collect(),
+            revenue: self.revenue.clone(),
+            limit_violations: self.limit_violations.clone(),
+            rows: self.rows,
+            next_synthetic_id: self.next_synthetic_id,
+            timestamp: self.timestamp,
+        };
+        let temporary_path = format!("{}.tmp", path);
+        let mut writer = BufWriter::new(File::create(&temporary_path)?);
+        serde_json::to_writer(&mut writer, &state)?;
+        writer.flush()?;
+        fs::rename(&temporary_path, path)?;
+        Ok(())
+    }
+
+    /// Loads the accounts and the counters of the bank saved by `save_state`,
+    /// replacing the current ones. The accounts use the configuration of the
+    /// bank, and the fraud rules start afresh.
+    pub fn load_state(&mut self, path: &str) -> Result<(), Box<dyn Error>> {
+        let state: State<Account> = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        self.accounts = state
+            .accounts
+            .into_iter()
+            .map(|mut account| {
+                account.set_config(self.config.clone());
+                (account.get_client_id(), account)
+            })
+            .collect();
+        self.revenue = state.revenue;
+        self.limit_violations = state.limit_violations;
+        self.rows = state.rows;
+        self.next_synthetic_id = state.next_synthetic_id;
+        self.timestamp = state.timestamp;
+        self.fraud = FraudEngine::with_rules(&self.config.fraud);
+        Ok(())
+    }
+
+    /// Creates a copy of the bank to try transactions on, without changing the
+    /// bank itself. The copy shares the handlers of the bank, but not its
+    /// observers, and its fraud rules start afresh.
+    pub fn scratch_copy(&self) -> Bank {
+        Bank {
+            accounts: self.accounts.clone(),
+            revenue: self.revenue.clone(),
+            limit_violations: self.limit_violations.clone(),
+            handlers: self.handlers.clone(),
+            observers: vec![],
+            fraud: FraudEngine::with_rules(&self.config.fraud),
+            // The scratch copy never writes to the log file of the bank.
+            logger: Logger::new(
+                self.config.log.level,
+                self.config.log.format.clone(),
+                Destination::Stderr,
+            ),
+            line: None,
+            rows: self.rows,
+            malformed_rows: self.malformed_rows,
+            next_synthetic_id: self.next_synthetic_id,
+            timestamp: self.timestamp,
+            config: self.config.clone(),
+        }
+    }
+
+    fn emit(&mut self, event: Event) {
+        for observer in self.observers.iter_mut() {
+            observer.notify(&event);
+        }
+    }
+
+    /// Emits the events of the disputes and of the expired authorizations
+    /// found in the movements of funds of an account.
+    fn emit_entries(&mut self, client_id: u16, entries: &[LedgerEntry]) {
+        for entry in entries {
+            let (transaction_id, currency) = (entry.transaction_id, entry.currency.clone());
+            let event = match (&entry.kind, &entry.r#type) {
+                (EntryKind::Expiry, _) => Event::AuthorizationExpired {
+                    client_id,
+                    transaction_id,
+                    currency,
+                    amount: -entry.reserved,
+                },
+                (EntryKind::Transaction, TransactionType::Dispute) => Event::DisputeOpened {
+                    client_id,
+                    transaction_id,
+                    currency,
+                    amount: entry.held,
+                },
+                (EntryKind::Transaction, TransactionType::Resolve) => Event::DisputeResolved {
+                    client_id,
+                    transaction_id,
+                    currency,
+                    amount: -entry.held,
+                },
+                (EntryKind::Transaction, TransactionType::Chargeback) => Event::ChargedBack {
+                    client_id,
+                    transaction_id,
+                    currency,
+                    amount: -entry.held,
+                },
+                _ => continue,
+            };
+            self.emit(event);
+        }
+    }
+
+    /// Releases the funds of the authorizations that expired in every account.
+    pub fn expire_authorizations(&mut self) {
+        let mut client_ids: Vec<u16> = self.accounts.keys().cloned().collect();
+        client_ids.sort();
+        for client_id in client_ids {
+            let account = self.accounts.get_mut(&client_id).unwrap();
+            let entries = account.expire_authorizations(self.rows, self.timestamp);
+            self.emit_entries(client_id, &entries);
+        }
+    }
+
+    /// Processes a transaction of the input. The transactions using the
+    /// IDs or the types reserved for the bank are rejected.
     pub fn process_transaction(&mut self, tx: Transaction) {
+        self.rows += 1;
+        if tx.transaction_id >= SYNTHETIC_TRANSACTION_ID_START || *tx.get_type() == TransactionType::Interest {
+            self.emit(Event::Received {
+                row: self.rows,
+                line: self.line,
+                client_id: tx.client_id,
+                transaction_id: tx.transaction_id,
+                r#type: tx.get_type().clone(),
+                destination: tx.destination,
+                synthetic: false,
+            });
+            let err = TransactionError::ReservedTransaction(tx.transaction_id);
+            self.reject(self.rows, &tx, err);
+            return;
+        }
+        self.apply_transaction(tx, false);
+    }
+
+    /// Credits interest on the available funds of every account, according
+    /// to the interest rates of the configuration. Every credit is posted as
+    /// an interest transaction, with an ID from the range reserved for the bank.
+    /// Locked accounts and negative funds do not accrue interest. The credits
+    /// do not count as rows of the input, so they do not make authorizations
+    /// expire. Fails when the reserved IDs are exhausted, after posting the
+    /// credits that still had an ID.
+    pub fn accrue_interest(&mut self) -> Result<(), Box<dyn Error>> {
+        let mut client_ids: Vec<u16> = self.accounts.keys().cloned().collect();
+        client_ids.sort();
+        for client_id in client_ids {
+            let account = &self.accounts[&client_id];
+            if account.is_locked() {
+                continue;
+            }
+            let mut credits: Vec<(String, Decimal)> = vec![];
+            for (currency, balance) in account.get_balances() {
+                if balance.available <= Decimal::ZERO {
+                    continue;
+                }
+                let rate = self.config.interest.get_rate(currency, balance.available);
+                let amount = balance.available * rate / Decimal::ONE_HUNDRED;
+                let amount = self.config.get_precision(currency).round(amount);
+                if amount > Decimal::ZERO {
+                    credits.push((currency.clone(), amount));
+                }
+            }
+            for (currency, amount) in credits {
+                let transaction_id = self.next_synthetic_id;
+                self.next_synthetic_id = transaction_id
+                    .checked_add(1)
+                    .ok_or("The transaction IDs reserved for the bank are exhausted.")?;
+                self.apply_transaction(
+                    Transaction {
+                        r#type: TransactionType::Interest,
+                        client_id,
+                        transaction_id,
+                        amount: Some(amount),
+                        currency: Some(currency),
+                        destination: None,
+                        timestamp: None,
+                    },
+                    true,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    // Applies a transaction at the current row. Synthetic transactions are
+    // posted by the bank rather than read from the input, so they have no line.
+    fn apply_transaction(&mut self, tx: Transaction, synthetic: bool) {
+        self.timestamp = self.timestamp.max(tx.timestamp);
+        let (row, timestamp) = (self.rows, tx.timestamp.or(self.timestamp));
+        let (client_id, transaction_id) = (tx.client_id, tx.transaction_id);
+        let r#type = tx.get_type().clone();
+        let expired = self
+            .get_account_mut(client_id)
+            .expire_authorizations(row, timestamp);
+        self.emit_entries(client_id, &expired);
+        self.emit(Event::Received {
+            row,
+            line: if synthetic { None } else { self.line },
+            client_id,
+            transaction_id,
+            r#type: r#type.clone(),
+            destination: tx.destination,
+            synthetic,
+        });
+
+        let account = self.get_account_mut(client_id);
+        let was_locked = account.is_locked();
+        let flags: Vec<String> = account.get_flags().iter().map(|flag| flag.to_string()).collect();
+        let is_untimed = tx.timestamp.is_none() && account.get_last_timestamp().is_none();
+
+        // The limits are based on the timestamps, so they cannot be checked without one.
+        let limits = self.config.get_limits(client_id);
+        let is_limited = limits.max_withdrawals.is_some() || limits.daily_withdrawal_amount.is_some();
+        let is_debit = matches!(
+            r#type,
+            TransactionType::Withdrawal | TransactionType::Transfer | TransactionType::Capture
+        );
+        if is_untimed && is_limited && is_debit {
+            self.logger.warn(
+                "The limits are not checked for a transaction without a timestamp.",
+                &[
+                    ("client", Value::from(client_id)),
+                    ("tx", Value::from(transaction_id)),
+                    ("line", Value::from(self.line)),
+                ],
+            );
+        }
+
+        let observed_tx = tx.clone();
+        let result = match tx.get_type() {
+            TransactionType::Transfer => self.process_transfer(tx),
+            TransactionType::Custom(name) => match self.handlers.get(name).cloned() {
+                Some(handler) => self
+                    .get_account_mut(tx.client_id)
+                    .process_custom_transaction(tx, handler.as_ref()),
+                None => Err(TransactionError::UnknownTransactionType(name.clone())),
+            },
+            _ => self.get_account_mut(tx.client_id).process_transaction(tx),
+        };
+
+        for alert in self.fraud.observe(&observed_tx, &result) {
+            let account = self.get_account_mut(alert.client_id);
+            account.raise_flag(&alert.flag);
+            if alert.hold {
+                account.raise_flag(HOLD_FLAG);
+            }
+        }
+        match result {
+            Ok(entries) => {
+                // The fees are moved to the revenue account of the bank.
+                for entry in &entries {
+                    if entry.kind == EntryKind::Fee || entry.kind == EntryKind::FeeRefund {
+                        *self.revenue.entry(entry.currency.clone()).or_default() -= entry.available;
+                    }
+                }
+                self.logger.debug(
+                    "Processed transaction.",
+                    &[
+                        ("client", Value::from(client_id)),
+                        ("tx", Value::from(transaction_id)),
+                        ("type", Value::from(r#type.get_name())),
+                        ("line", Value::from(self.line)),
+                    ],
+                );
+                self.emit(Event::Applied {
+                    row,
+                    client_id,
+                    transaction_id,
+                    r#type,
+                    entries: entries.clone(),
+                });
+                self.emit_entries(client_id, &entries);
+            }
+            Err(err) => self.reject(row, &observed_tx, err),
+        };
+
+        let account = self.get_account_mut(client_id);
+        let is_locked = account.is_locked();
+        let new_flags: Vec<String> = account
+            .get_flags()
+            .into_iter()
+            .filter(|flag| !flags.iter().any(|f| f == flag))
+            .map(|flag| flag.to_string())
+            .collect();
+        if is_locked && !was_locked {
+            self.logger.info(
+                "Locked account.",
+                &[
+                    ("client", Value::from(client_id)),
+                    ("tx", Value::from(transaction_id)),
+                ],
+            );
+            self.emit(Event::AccountLocked {
+                client_id,
+                transaction_id,
+            });
+        }
+        for flag in new_flags {
+            self.logger.warn(
+                "Flagged account.",
+                &[
+                    ("client", Value::from(client_id)),
+                    ("tx", Value::from(transaction_id)),
+                    ("flag", Value::from(flag.as_str())),
+                ],
+            );
+            self.emit(Event::AccountFlagged { client_id, flag });
+        }
+    }
+
+    /// Logs and counts a rejected transaction.
+    fn reject(&mut self, row: u64, tx: &Transaction, err: TransactionError) {
+        self.logger.warn(
+            &format!("Could not process transaction {}: {}", tx.transaction_id, err),
+            &[
+                ("client", Value::from(tx.client_id)),
+                ("tx", Value::from(tx.transaction_id)),
+                ("type", Value::from(tx.get_type().get_name())),
+                ("reason", Value::from(err.get_code())),
+                ("line", Value::from(self.line)),
+            ],
+        );
+        if err.is_limit_violation() {
+            *self.limit_violations.entry(tx.client_id).or_default() += 1;
+        }
+        self.emit(Event::Rejected {
+            row,
+            client_id: tx.client_id,
+            transaction_id: tx.transaction_id,
+            r#type: tx.get_type().clone(),
+            reason: err.get_code().to_string(),
+            message: err.to_string(),
+        });
+    }
+
+    /// Processes a transfer atomically: the destination account is only
+    /// credited if the source account was debited, and the source account
+    /// is only debited if the destination account can be credited.
+    fn process_transfer(&mut self, tx: Transaction) -> Result<Vec<LedgerEntry>, TransactionError> {
+        let destination_id = match tx.destination {
+            Some(id) if id == tx.client_id => return Err(TransactionError::InvalidDestination(id)),
+            Some(id) => id,
+            None => return Err(TransactionError::MissingDestination),
+        };
+        // The account of the destination is only created once the source is debited.
+        if self.accounts.get(&destination_id).is_some_and(Account::is_locked) {
+            return Err(TransactionError::DestinationLocked(destination_id));
+        }
+
+        // TODO make this critical section atomic.
+        // BEGIN CRITICAL SECTION
+        let mut entries = self
+            .get_account_mut(tx.client_id)
+            .process_transaction(tx.clone())?;
+        let debit = entries
+            .iter()
+            .find(|entry| entry.kind == EntryKind::Transaction)
+            .unwrap();
+        let (currency, amount) = (debit.currency.clone(), -debit.available);
+        let destination = self.get_account_mut(destination_id);
+        entries.extend(destination.receive_transfer(&tx, &currency, amount));
+        // END CRITICAL SECTION
+
+        Ok(entries)
+    }
+
+    fn get_account_mut(&mut self, client_id: u16) -> &mut Account {
         // Creating a new account if it doesn't exist could be made more efficient by
         // using the HashMap::try_insert function, so that only one search is performed
         // on the B-Tree. This feature is still experimental so I decided not to use it
         // at the moment.
-        let account: &mut Account = match self.accounts.get_mut(&tx.client_id) {
-            Some(a) => a,
-            None => {
-                self.accounts.insert(tx.client_id, Account::new(tx.client_id));
-                self.accounts.get_mut(&tx.client_id).unwrap()
-            }
-        };
-        account.process_transaction(tx);
+        if !self.accounts.contains_key(&client_id) {
+            let account = Account::with_config(client_id, self.config.clone());
+            self.accounts.insert(client_id, account);
+        }
+        self.accounts.get_mut(&client_id).unwrap()
+    }
+
+    /// Audits all the accounts handled by the bank, ordered by customer ID.
+    pub fn audit(&self) -> Vec<AuditIssue> {
+        let mut client_ids: Vec<&u16> = self.accounts.keys().collect();
+        client_ids.sort();
+        client_ids
+            .into_iter()
+            .flat_map(|client_id| self.accounts[client_id].audit())
+            .collect()
+    }
+
+    /// Prints the number of transactions rejected by the limits to `stderr`.
+    pub fn print_limit_violations(&self) {
+        let total: u64 = self.limit_violations.values().sum();
+        if total == 0 {
+            return;
+        }
+        eprintln!("{} transactions were rejected by the limits.", total);
+        for (client_id, count) in &self.limit_violations {
+            eprintln!(
+                "Client {}: {} transactions rejected by the limits.",
+                client_id, count
+            );
+        }
     }
 
     pub fn print(&self) {
-        println!("client, available, held, total, locked");
+        println!("client, currency, available, held, reserved, total, locked, flags");
 
         for account in self.accounts.values() {
             account.print();
         }
     }
+
+    /// Gets the disputes whose funds are still held, ordered by customer
+    /// and transaction ID.
+    pub fn get_open_disputes(&self) -> Vec<OpenDispute> {
+        let mut client_ids: Vec<&u16> = self.accounts.keys().collect();
+        client_ids.sort();
+        client_ids
+            .into_iter()
+            .flat_map(|client_id| self.accounts[client_id].get_open_disputes())
+            .collect()
+    }
+
+    /// Gets the state of every account, ordered by customer ID.
+    pub fn get_rows(&self) -> Vec<AccountRow> {
+        let mut client_ids: Vec<&u16> = self.accounts.keys().collect();
+        client_ids.sort();
+        client_ids
+            .into_iter()
+            .flat_map(|client_id| self.accounts[client_id].get_rows())
+            .collect()
+    }
+
+    /// Prints the accounts as a JSON array, which can be read back
+    /// with `snapshot::read_snapshot`.
+    pub fn print_json(&self) {
+        println!("{}", serde_json::to_string_pretty(&self.get_rows()).unwrap());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::account::AccountView;
+    use crate::config::{Fee, RapidWithdrawalRule, TransferDisputePolicy};
+    use crate::observer::MemorySink;
+    use crate::test_support::{transaction, transfer};
+    use crate::transaction::TransactionType;
+
+    fn get_available(bank: &Bank, client_id: u16) -> Decimal {
+        bank.accounts[&client_id].get_balance("USD").available
+    }
+
+    #[test]
+    pub fn test_transfer() {
+        let mut bank = Bank::new();
+        bank.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some("100.0")));
+        bank.process_transaction(transfer(1, 2, "40.0", 2));
+
+        assert_eq!(get_available(&bank, 1), Decimal::from_str("60.0").unwrap());
+        assert_eq!(get_available(&bank, 2), Decimal::from_str("40.0").unwrap());
+        assert_eq!(bank.audit(), vec![]);
+    }
+
+    #[test]
+    pub fn test_transfer_insufficient_funds() {
+        let mut bank = Bank::new();
+        bank.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some("100.0")));
+        bank.process_transaction(transfer(1, 2, "140.0", 2));
+
+        assert_eq!(get_available(&bank, 1), Decimal::from_str("100.0").unwrap());
+        assert!(!bank.accounts.contains_key(&2));
+    }
+
+    #[test]
+    pub fn test_transfer_locked_destination() {
+        let mut bank = Bank::new();
+        bank.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some("100.0")));
+        bank.process_transaction(transaction(TransactionType::Deposit, 2, 2, Some("10.0")));
+        bank.process_transaction(transaction(TransactionType::Dispute, 2, 2, None));
+        bank.process_transaction(transaction(TransactionType::Chargeback, 2, 2, None));
+        bank.process_transaction(transfer(1, 3, "40.0", 2));
+
+        assert_eq!(get_available(&bank, 1), Decimal::from_str("100.0").unwrap());
+        assert_eq!(get_available(&bank, 2), Decimal::from_str("0.0").unwrap());
+    }
+
+    #[test]
+    pub fn test_dispute_transfer() {
+        let mut bank = Bank::new();
+        bank.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some("100.0")));
+        bank.process_transaction(transfer(1, 2, "40.0", 2));
+        bank.process_transaction(transaction(TransactionType::Dispute, 1, 2, None));
+        assert_eq!(
+            bank.accounts[&1].get_balance("USD").held,
+            Decimal::from_str("0.0").unwrap()
+        );
+
+        let config = Config {
+            transfer_disputes: TransferDisputePolicy::Source,
+            ..Config::default()
+        };
+        let mut bank = Bank::with_config(config);
+        bank.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some("100.0")));
+        bank.process_transaction(transfer(1, 2, "40.0", 2));
+        bank.process_transaction(transaction(TransactionType::Dispute, 1, 2, None));
+        assert_eq!(get_available(&bank, 1), Decimal::from_str("20.0").unwrap());
+        assert_eq!(
+            bank.accounts[&1].get_balance("USD").held,
+            Decimal::from_str("40.0").unwrap()
+        );
+        assert_eq!(get_available(&bank, 2), Decimal::from_str("40.0").unwrap());
+        assert_eq!(bank.audit(), vec![]);
+    }
+
+    #[test]
+    pub fn test_limit_violations() {
+        let log_path = std::env::temp_dir().join(format!("tx-processor-limits-{}.log", std::process::id()));
+        let mut config = Config::default();
+        config.limits.max_withdrawals = Some(1);
+        config.log.file = Some(log_path.to_str().unwrap().to_string());
+        let mut bank = Bank::with_config(config);
+        for client_id in 1..3 {
+            let transaction_id = client_id as u32 * 10;
+            let deposit = transaction(TransactionType::Deposit, client_id, transaction_id, Some("10.0"));
+            bank.process_transaction(Transaction {
+                timestamp: Some(0),
+                ..deposit
+            });
+            for i in 1..=client_id as u32 {
+                bank.process_transaction(transaction(
+                    TransactionType::Withdrawal,
+                    client_id,
+                    transaction_id + i,
+                    Some("1.0"),
+                ));
+            }
+        }
+        // Withdrawals without a timestamp happen at the time of the deposit.
+        assert_eq!(bank.limit_violations.get(&1), None);
+        assert_eq!(bank.limit_violations[&2], 1);
+        assert_eq!(get_available(&bank, 2), Decimal::from_str("9.0").unwrap());
+
+        // Without any timestamp, the limits cannot be checked.
+        bank.process_transaction(transaction(TransactionType::Deposit, 3, 30, Some("10.0")));
+        bank.process_transaction(transaction(TransactionType::Withdrawal, 3, 31, Some("1.0")));
+        bank.process_transaction(transaction(TransactionType::Withdrawal, 3, 32, Some("1.0")));
+        assert_eq!(get_available(&bank, 3), Decimal::from_str("8.0").unwrap());
+        drop(bank);
+        let log = std::fs::read_to_string(&log_path).unwrap();
+        std::fs::remove_file(&log_path).unwrap();
+        let warnings: Vec<&str> = log
+            .lines()
+            .filter(|line| line.contains("The limits are not checked"))
+            .collect();
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings.iter().all(|line| line.contains("client=3")));
+    }
+
+    #[test]
+    pub fn test_fraud_rules() {
+        let mut config = Config::default();
+        config.fraud.rapid_withdrawal = Some(RapidWithdrawalRule {
+            hold: true,
+            ..RapidWithdrawalRule::default()
+        });
+        let mut bank = Bank::with_config(config);
+        bank.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some("100.0")));
+        bank.process_transaction(transaction(TransactionType::Withdrawal, 1, 2, Some("95.0")));
+        bank.process_transaction(transaction(TransactionType::Deposit, 1, 3, Some("100.0")));
+        bank.process_transaction(transaction(TransactionType::Withdrawal, 1, 4, Some("10.0")));
+
+        assert_eq!(get_available(&bank, 1), Decimal::from_str("105.0").unwrap());
+        assert_eq!(bank.accounts[&1].get_flags(), vec!["hold", "rapid_withdrawal"]);
+    }
+
+    // Credits or debits the amount of the transaction, depending on the sign of the amount.
+    struct Adjustment;
+    impl TransactionHandler for Adjustment {
+        fn process(&self, tx: &Transaction, account: &mut AccountView) -> Result<(), TransactionError> {
+            let currency = account.get_currency();
+            match tx.amount {
+                Some(amount) if amount < Decimal::ZERO => account.debit(&currency, -amount),
+                Some(amount) => account.credit(&currency, amount),
+                None => Err(TransactionError::MissingAmount),
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_custom_transaction() {
+        let adjustment = TransactionType::Custom("adjustment".to_string());
+        let mut bank = Bank::new();
+        bank.process_transaction(transaction(adjustment.clone(), 1, 1, Some("10.0")));
+        assert_eq!(get_available(&bank, 1), Decimal::ZERO);
+
+        bank.register_handler("Adjustment", Adjustment).unwrap();
+        bank.process_transaction(transaction(adjustment.clone(), 1, 2, Some("10.0")));
+        bank.process_transaction(transaction(adjustment.clone(), 1, 3, Some("-4.0")));
+        bank.process_transaction(transaction(adjustment, 1, 4, Some("-40.0")));
+        assert_eq!(get_available(&bank, 1), Decimal::from_str("6.0").unwrap());
+        assert_eq!(bank.audit(), vec![]);
+
+        // Built-in types cannot be handled.
+        for name in ["deposit", "Withdrawal", "dispute", "interest", "in valid"] {
+            assert!(bank.register_handler(name, Adjustment).is_err(), "{}", name);
+        }
+        bank.process_transaction(transaction(TransactionType::Deposit, 1, 5, Some("1.0")));
+        assert_eq!(get_available(&bank, 1), Decimal::from_str("7.0").unwrap());
+    }
+
+    #[test]
+    pub fn test_events() {
+        let mut bank = Bank::new();
+        let sink = MemorySink::new();
+        bank.add_observer(sink.clone());
+        bank.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some("10.0")));
+        bank.process_transaction(transaction(TransactionType::Withdrawal, 1, 2, Some("20.0")));
+        bank.process_transaction(transaction(TransactionType::Dispute, 1, 1, None));
+        bank.process_transaction(transaction(TransactionType::Chargeback, 1, 1, None));
+
+        let events = sink.get_events();
+        assert_eq!(
+            events[0],
+            Event::Received {
+                row: 1,
+                line: None,
+                client_id: 1,
+                transaction_id: 1,
+                r#type: TransactionType::Deposit,
+                destination: None,
+                synthetic: false,
+            }
+        );
+        assert!(matches!(&events[1], Event::Applied { entries, .. } if entries.len() == 1));
+        assert_eq!(
+            events[3],
+            Event::Rejected {
+                row: 2,
+                client_id: 1,
+                transaction_id: 2,
+                r#type: TransactionType::Withdrawal,
+                reason: "insufficient_funds".to_string(),
+                message: "Insufficient amount: 10.0 is available, 20.0 was requested.".to_string(),
+            }
+        );
+        let amount = Decimal::from_str("10.0").unwrap();
+        assert_eq!(
+            events[6],
+            Event::DisputeOpened {
+                client_id: 1,
+                transaction_id: 1,
+                currency: "USD".to_string(),
+                amount,
+            }
+        );
+        assert_eq!(
+            events[9],
+            Event::ChargedBack {
+                client_id: 1,
+                transaction_id: 1,
+                currency: "USD".to_string(),
+                amount,
+            }
+        );
+        assert_eq!(
+            events[10],
+            Event::AccountLocked {
+                client_id: 1,
+                transaction_id: 1,
+            }
+        );
+        assert_eq!(events.len(), 11);
+    }
+
+    #[test]
+    pub fn test_accrue_interest() {
+        let config: Config = serde_json::from_str(
+            r#"{
+                "interest": {"tiers": [{"percent": "1.5"}]},
+                "currency_precisions": {"USD": {"decimals": 2}}
+            }"#,
+        )
+        .unwrap();
+        let mut bank = Bank::with_config(config);
+        bank.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some("100.3")));
+        bank.process_transaction(transaction(TransactionType::Deposit, 2, 2, Some("10.0")));
+        bank.process_transaction(transaction(TransactionType::Dispute, 2, 2, None));
+        bank.process_transaction(transaction(TransactionType::Deposit, 3, 3, Some("10.0")));
+        bank.process_transaction(transaction(TransactionType::Dispute, 3, 3, None));
+        bank.process_transaction(transaction(TransactionType::Chargeback, 3, 3, None));
+        bank.process_transaction(transaction(TransactionType::Deposit, 4, 4, Some("200.0")));
+        let sink = MemorySink::new();
+        bank.add_observer(sink.clone());
+        bank.accrue_interest().unwrap();
+
+        // The credits do not consume rows, and have no line.
+        assert_eq!(bank.rows, 7);
+        assert!(matches!(
+            &sink.get_events()[0],
+            Event::Received {
+                row: 7,
+                line: None,
+                synthetic: true,
+                ..
+            }
+        ));
+
+        // 1.5% of 100.3 is 1.5045, rounded to 2 digits with banker's rounding.
+        assert_eq!(get_available(&bank, 1), Decimal::from_str("101.80").unwrap());
+        assert_eq!(get_available(&bank, 2), Decimal::ZERO);
+        assert_eq!(get_available(&bank, 4), Decimal::from_str("203.0").unwrap());
+        assert_eq!(
+            bank.accounts[&4]
+                .get_transaction(SYNTHETIC_TRANSACTION_ID_START + 1)
+                .unwrap()
+                .amount,
+            Some(Decimal::from_str("3.00").unwrap())
+        );
+        assert_eq!(bank.audit(), vec![]);
+
+        // The credits stop when the reserved IDs are exhausted.
+        bank.next_synthetic_id = u32::MAX;
+        assert!(bank.accrue_interest().is_err());
+        assert_eq!(get_available(&bank, 1), Decimal::from_str("101.80").unwrap());
+    }
+
+    #[test]
+    pub fn test_reserved_transactions() {
+        let mut bank = Bank::new();
+        let sink = MemorySink::new();
+        bank.add_observer(sink.clone());
+        bank.process_transaction(transaction(
+            TransactionType::Deposit,
+            1,
+            SYNTHETIC_TRANSACTION_ID_START,
+            Some("10.0"),
+        ));
+        bank.process_transaction(transaction(TransactionType::Interest, 1, 1, Some("10.0")));
+        assert!(bank.accounts.is_empty());
+        assert!(matches!(
+            &sink.get_events()[3],
+            Event::Rejected { reason, .. } if reason == "reserved_transaction"
+        ));
+    }
+
+    #[test]
+    pub fn test_malformed_rows() {
+        let path = std::env::temp_dir().join(format!("tx-processor-malformed-{}.csv", std::process::id()));
+        std::fs::write(
+            &path,
+            "type,client,tx,amount\ndeposit,1,1,10.0\ndeposit,one,2,10.0\ndeposit,1\ndeposit,1,3,5.0\n",
+        )
+        .unwrap();
+        // By default, the first malformed row stops the processing.
+        let mut bank = Bank::new();
+        assert!(bank.process_transactions(path.to_str().unwrap()).is_err());
+        assert_eq!(get_available(&bank, 1), Decimal::from_str("10.0").unwrap());
+
+        let mut bank = Bank::with_config(Config {
+            skip_malformed_rows: true,
+            ..Config::default()
+        });
+        bank.process_transactions(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(get_available(&bank, 1), Decimal::from_str("15.0").unwrap());
+        assert_eq!(bank.get_malformed_rows(), 2);
+    }
+
+    #[test]
+    pub fn test_fee_revenue() {
+        let mut config = Config::default();
+        config.fees.withdrawal = Some(Fee::Flat {
+            amount: Decimal::from_str("1.5").unwrap(),
+        });
+        let mut bank = Bank::with_config(config);
+
+        for client_id in 1..3 {
+            let transaction_id = client_id as u32 * 10;
+            bank.process_transaction(transaction(
+                TransactionType::Deposit,
+                client_id,
+                transaction_id,
+                Some("10.0"),
+            ));
+            bank.process_transaction(transaction(
+                TransactionType::Withdrawal,
+                client_id,
+                transaction_id + 1,
+                Some("5.0"),
+            ));
+        }
+
+        assert_eq!(bank.revenue["USD"], Decimal::from_str("3.0").unwrap());
+        assert_eq!(
+            bank.accounts[&1].get_balance("USD").available,
+            Decimal::from_str("3.5").unwrap()
+        );
+    }
+
+    #[test]
+    pub fn test_state() {
+        let mut bank = Bank::new();
+        bank.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some("10.0")));
+        bank.process_transaction(Transaction {
+            timestamp: Some(1_650_000_000),
+            ..transaction(TransactionType::Deposit, 2, 2, Some("5.0"))
+        });
+        bank.process_transaction(transaction(TransactionType::Dispute, 2, 2, None));
+        // Timestamps that cannot be written as RFC 3339 dates are kept.
+        for (transaction_id, timestamp) in [(3, i64::MIN), (4, 999_999_999_999), (5, i64::MAX)] {
+            bank.process_transaction(Transaction {
+                timestamp: Some(timestamp),
+                ..transaction(TransactionType::Deposit, 3, transaction_id, Some("1.0"))
+            });
+        }
+
+        let path = std::env::temp_dir().join(format!("tx-processor-state-{}.json", std::process::id()));
+        bank.save_state(path.to_str().unwrap()).unwrap();
+        let mut loaded = Bank::new();
+        loaded.load_state(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.get_rows(), bank.get_rows());
+        assert_eq!(
+            loaded.accounts[&3].get_transaction(5).unwrap().timestamp,
+            Some(i64::MAX)
+        );
+        assert!(loaded.audit().is_empty());
+        // The history of the accounts is kept, so earlier transactions can still be disputed.
+        loaded.process_transaction(transaction(TransactionType::Chargeback, 2, 2, None));
+        loaded.process_transaction(transaction(TransactionType::Dispute, 1, 1, None));
+        assert!(loaded.accounts[&2].is_locked());
+        assert_eq!(get_available(&loaded, 1), Decimal::ZERO);
+    }
+
+    #[test]
+    pub fn test_scratch_copy() {
+        let log_path = std::env::temp_dir().join(format!("tx-processor-scratch-{}.log", std::process::id()));
+        let mut config = Config::default();
+        config.log.file = Some(log_path.to_str().unwrap().to_string());
+        let mut bank = Bank::with_config(config);
+        bank.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some("10.0")));
+        let mut scratch = bank.scratch_copy();
+        scratch.process_transaction(transaction(TransactionType::Withdrawal, 1, 2, Some("4.0")));
+        scratch.process_transaction(transaction(TransactionType::Deposit, 2, 3, Some("1.0")));
+
+        assert_eq!(get_available(&scratch, 1), Decimal::from_str("6.0").unwrap());
+        assert_eq!(get_available(&bank, 1), Decimal::from_str("10.0").unwrap());
+        assert!(!bank.accounts.contains_key(&2));
+
+        // The rejections of the scratch copy are not logged to the log file.
+        scratch.process_transaction(transaction(TransactionType::Withdrawal, 1, 4, Some("40.0")));
+        let log = std::fs::read_to_string(&log_path).unwrap();
+        std::fs::remove_file(&log_path).unwrap();
+        assert!(log.is_empty());
+    }
+
+    #[test]
+    pub fn test_as_of() {
+        let path = std::env::temp_dir().join(format!("tx-processor-as-of-{}.csv", std::process::id()));
+        std::fs::write(
+            &path,
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             deposit,1,2,5.0\n\
+             dispute,1,1,\n\
+             deposit,2,3,1.0\n\
+             resolve,1,1,\n",
+        )
+        .unwrap();
+        let path = path.to_str().unwrap();
+
+        let mut bank = Bank::new();
+        assert!(bank.process_transactions_as_of(path, &AsOf::Line(4)).unwrap());
+        assert_eq!(get_available(&bank, 1), Decimal::from_str("5.0").unwrap());
+        assert_eq!(
+            bank.get_open_disputes(),
+            vec![OpenDispute {
+                client_id: 1,
+                transaction_id: 1,
+                currency: "USD".to_string(),
+                held: Decimal::from_str("10.0").unwrap(),
+            }]
+        );
+        assert!(!bank.accounts.contains_key(&2));
+
+        let mut bank = Bank::new();
+        assert!(bank
+            .process_transactions_as_of(path, &AsOf::Transaction(3))
+            .unwrap());
+        assert_eq!(bank.get_open_disputes().len(), 1);
+        assert!(bank.accounts.contains_key(&2));
+
+        let mut bank = Bank::new();
+        assert!(!bank
+            .process_transactions_as_of(path, &AsOf::Transaction(42))
+            .unwrap());
+        assert!(bank.get_open_disputes().is_empty());
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(AsOf::from_string("line:12"), Ok(AsOf::Line(12)));
+        assert_eq!(AsOf::from_string("tx:42"), Ok(AsOf::Transaction(42)));
+        assert!(AsOf::from_string("42").is_err());
+        assert!(AsOf::from_string("tx:x").is_err());
+    }
 }
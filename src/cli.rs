@@ -0,0 +1,355 @@
+use crate::bank::AsOf;
+use crate::snapshot::OutputFormat;
+
+/// The command run by the processor, given as the first argument.
+/// Processing the transactions is the default command. The arguments
+/// following `--` are always paths.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub enum Command {
+    /// Processes the transactions and prints the accounts.
+    Process { transactions_file_path: String },
+    /// Processes the transactions, accrues interest on every account,
+    /// and prints the accounts.
+    AccrueInterest { transactions_file_path: String },
+    /// Compares two outputs of the accounts.
+    Diff {
+        left_file_path: String,
+        right_file_path: String,
+    },
+    /// Processes the transactions and compares the accounts with the
+    /// balances expected by another system.
+    Reconcile {
+        transactions_file_path: String,
+        expected_file_path: String,
+    },
+    /// Processes the transactions and prints every row touching a customer,
+    /// with the decision taken and the balances that followed.
+    Trace {
+        client_id: u16,
+        transactions_file_path: String,
+    },
+    /// Prints generated transactions as CSV, from the settings of the file if
+    /// any. The seed and the number of rows override the settings.
+    Generate {
+        settings_file_path: Option<String>,
+        seed: Option<u64>,
+        rows: Option<u64>,
+    },
+}
+
+// The names of the commands. Processing the transactions is also the
+// command run when none is named.
+const COMMANDS: [&str; 6] = ["process", "interest", "diff", "reconcile", "trace", "generate"];
+
+/// The arguments of the processor.
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct Options {
+    pub command: Command,
+
+    pub audit: bool,
+
+    pub config_file_path: Option<String>,
+
+    pub events_file_path: Option<String>,
+
+    /// The file the metrics are written to at the end of the run, in the
+    /// Prometheus text format.
+    pub metrics_file_path: Option<String>,
+
+    /// The address the metrics are served on, over HTTP. The processor
+    /// keeps serving them once the transactions are processed.
+    pub metrics_address: Option<String>,
+
+    /// The file the summary of the run is written to, as JSON. The
+    /// summary is printed to `stderr` by default.
+    pub summary_file_path: Option<String>,
+
+    /// The format the accounts are printed in, CSV by default.
+    pub output_format: OutputFormat,
+
+    /// The file the state of the bank is loaded from, if it exists, and
+    /// saved to after processing the transactions.
+    pub state_file_path: Option<String>,
+
+    /// Whether to only report the effect of the transactions, without
+    /// saving the state.
+    pub dry_run: bool,
+
+    /// The point of the input at which the processing stops.
+    pub as_of: Option<AsOf>,
+
+    /// Whether the malformed rows of the input are skipped rather than
+    /// stopping the processing.
+    pub skip_malformed: bool,
+}
+impl Options {
+    pub fn parse(args: impl Iterator<Item = String>) -> Result<Options, String> {
+        let mut command_name: Option<String> = None;
+        let mut audit = false;
+        let mut config_file_path: Option<String> = None;
+        let mut events_file_path: Option<String> = None;
+        let mut metrics_file_path: Option<String> = None;
+        let mut metrics_address: Option<String> = None;
+        let mut summary_file_path: Option<String> = None;
+        let mut output_format = OutputFormat::Csv;
+        let mut state_file_path: Option<String> = None;
+        let mut dry_run = false;
+        let mut as_of: Option<AsOf> = None;
+        let mut skip_malformed = false;
+        let mut seed: Option<u64> = None;
+        let mut rows: Option<u64> = None;
+        let mut paths: Vec<String> = vec![];
+
+        let mut args = args.peekable();
+        if args.peek().is_some_and(|arg| COMMANDS.contains(&arg.as_str())) {
+            command_name = args.next();
+        }
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--audit" => audit = true,
+                "--config" => config_file_path = Some(get_value(&arg, args.next())?),
+                "--events" => events_file_path = Some(get_value(&arg, args.next())?),
+                "--metrics" => metrics_file_path = Some(get_value(&arg, args.next())?),
+                "--metrics-listen" => metrics_address = Some(get_value(&arg, args.next())?),
+                "--summary" => summary_file_path = Some(get_value(&arg, args.next())?),
+                "--state" => state_file_path = Some(get_value(&arg, args.next())?),
+                "--dry-run" => dry_run = true,
+                "--skip-malformed" => skip_malformed = true,
+                "--as-of" => as_of = Some(AsOf::from_string(&get_value(&arg, args.next())?)?),
+                "--seed" => seed = Some(get_number(&arg, args.next())?),
+                "--rows" => rows = Some(get_number(&arg, args.next())?),
+                "--output" => {
+                    let format = get_value(&arg, args.next())?;
+                    output_format = match OutputFormat::from_string(&format) {
+                        Some(format) => format,
+                        None => return Err(format!("Unknown output format {}.", format)),
+                    };
+                }
+                "--" => paths.extend(args.by_ref()),
+                _ if arg.starts_with("--") => return Err(format!("Unknown option {}.", arg)),
+                _ => paths.push(arg),
+            }
+        }
+
+        let command = match command_name.as_deref() {
+            Some("diff") => {
+                if paths.len() != 2 {
+                    return Err("You must provide the paths to the two account files to compare.".to_string());
+                }
+                Command::Diff {
+                    right_file_path: paths.remove(1),
+                    left_file_path: paths.remove(0),
+                }
+            }
+            Some("reconcile") => {
+                if paths.len() != 2 {
+                    return Err(
+                        "You must provide the paths to a transaction file and to an expected balance file."
+                            .to_string(),
+                    );
+                }
+                Command::Reconcile {
+                    expected_file_path: paths.remove(1),
+                    transactions_file_path: paths.remove(0),
+                }
+            }
+            Some("generate") => {
+                if paths.len() > 1 {
+                    return Err("You can only provide the path to a settings file.".to_string());
+                }
+                Command::Generate {
+                    settings_file_path: paths.pop(),
+                    seed: seed.take(),
+                    rows: rows.take(),
+                }
+            }
+            Some("trace") => {
+                if paths.len() != 2 {
+                    return Err("You must provide a client ID and the path to a transaction file.".to_string());
+                }
+                let client_id = match paths[0].parse() {
+                    Ok(client_id) => client_id,
+                    Err(_) => return Err(format!("Invalid client ID {}.", paths[0])),
+                };
+                Command::Trace {
+                    client_id,
+                    transactions_file_path: paths.remove(1),
+                }
+            }
+            name => {
+                if paths.len() != 1 {
+                    return Err("You must provide the path to a transaction file to process.".to_string());
+                }
+                let transactions_file_path = paths.remove(0);
+                match name {
+                    Some("interest") => Command::AccrueInterest {
+                        transactions_file_path,
+                    },
+                    _ => Command::Process {
+                        transactions_file_path,
+                    },
+                }
+            }
+        };
+        if seed.is_some() || rows.is_some() {
+            return Err("Options --seed and --rows can only be used when generating transactions.".to_string());
+        }
+        if dry_run && !matches!(command, Command::Process { .. }) {
+            return Err("Option --dry-run can only be used when processing transactions.".to_string());
+        }
+        if as_of.is_some()
+            && (dry_run || state_file_path.is_some() || !matches!(command, Command::Process { .. }))
+        {
+            return Err(
+                "Option --as-of can only be used when processing transactions, without --dry-run or --state."
+                    .to_string(),
+            );
+        }
+        Ok(Options {
+            command,
+            audit,
+            config_file_path,
+            events_file_path,
+            metrics_file_path,
+            metrics_address,
+            summary_file_path,
+            output_format,
+            state_file_path,
+            dry_run,
+            as_of,
+            skip_malformed,
+        })
+    }
+}
+
+fn get_value(option: &str, value: Option<String>) -> Result<String, String> {
+    match value {
+        Some(value) => Ok(value),
+        None => Err(format!("Option {} requires a value.", option)),
+    }
+}
+
+fn get_number(option: &str, value: Option<String>) -> Result<u64, String> {
+    let value = get_value(option, value)?;
+    match value.parse() {
+        Ok(number) => Ok(number),
+        Err(_) => Err(format!("Option {} requires a number, got {}.", option, value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Options, String> {
+        Options::parse(args.split_whitespace().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    pub fn test_parse_options() {
+        assert_eq!(
+            parse("--audit transactions.csv --config config.json"),
+            Ok(Options {
+                command: Command::Process {
+                    transactions_file_path: "transactions.csv".to_string(),
+                },
+                audit: true,
+                config_file_path: Some("config.json".to_string()),
+                events_file_path: None,
+                metrics_file_path: None,
+                metrics_address: None,
+                summary_file_path: None,
+                output_format: OutputFormat::Csv,
+                state_file_path: None,
+                dry_run: false,
+                as_of: None,
+                skip_malformed: false,
+            })
+        );
+        assert_eq!(
+            parse("interest transactions.csv").unwrap().command,
+            Command::AccrueInterest {
+                transactions_file_path: "transactions.csv".to_string()
+            }
+        );
+        assert_eq!(
+            parse("diff left.csv right.json").unwrap().command,
+            Command::Diff {
+                left_file_path: "left.csv".to_string(),
+                right_file_path: "right.json".to_string(),
+            }
+        );
+        assert_eq!(
+            parse("reconcile transactions.csv expected.csv").unwrap().command,
+            Command::Reconcile {
+                transactions_file_path: "transactions.csv".to_string(),
+                expected_file_path: "expected.csv".to_string(),
+            }
+        );
+        assert_eq!(
+            parse("trace 7 transactions.csv").unwrap().command,
+            Command::Trace {
+                client_id: 7,
+                transactions_file_path: "transactions.csv".to_string(),
+            }
+        );
+        assert_eq!(
+            parse("generate settings.json --seed 42").unwrap().command,
+            Command::Generate {
+                settings_file_path: Some("settings.json".to_string()),
+                seed: Some(42),
+                rows: None,
+            }
+        );
+        assert_eq!(
+            parse("--output json transactions.csv").unwrap().output_format,
+            OutputFormat::Json
+        );
+        let options = parse("--state state.json --dry-run corrections.csv").unwrap();
+        assert_eq!(options.state_file_path, Some("state.json".to_string()));
+        assert!(options.dry_run);
+        assert!(parse("--skip-malformed transactions.csv").unwrap().skip_malformed);
+        assert_eq!(
+            parse("--as-of tx:42 transactions.csv").unwrap().as_of,
+            Some(AsOf::Transaction(42))
+        );
+        let options = parse("transactions.csv --metrics metrics.prom --metrics-listen 127.0.0.1:9100").unwrap();
+        assert_eq!(options.metrics_file_path, Some("metrics.prom".to_string()));
+        assert_eq!(options.metrics_address, Some("127.0.0.1:9100".to_string()));
+
+        // A transaction file can be named like a command or an option.
+        for args in ["process interest", "-- interest", "--audit -- --audit"] {
+            let path = args.rsplit(' ').next().unwrap().to_string();
+            assert_eq!(
+                parse(args).unwrap().command,
+                Command::Process {
+                    transactions_file_path: path,
+                },
+                "{}",
+                args
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_parse_invalid_options() {
+        assert!(parse("").is_err());
+        assert!(parse("interest").is_err());
+        assert!(parse("process").is_err());
+        assert!(parse("transactions.csv --events").is_err());
+        assert!(parse("transactions.csv --unknown").is_err());
+        assert!(parse("transactions.csv other.csv").is_err());
+        assert!(parse("diff left.csv").is_err());
+        assert!(parse("reconcile transactions.csv").is_err());
+        assert!(parse("trace client transactions.csv").is_err());
+        assert!(parse("generate --rows many").is_err());
+        assert!(parse("--seed 42 transactions.csv").is_err());
+        assert!(parse("interest --dry-run transactions.csv").is_err());
+        assert!(parse("--as-of 12 transactions.csv").is_err());
+        assert!(parse("--as-of line:12 --state state.json transactions.csv").is_err());
+        assert!(parse("--output xml transactions.csv").is_err());
+    }
+}
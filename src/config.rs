@@ -0,0 +1,664 @@
+use rust_decimal::prelude::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+
+use crate::error::TransactionError;
+use crate::log::LogConfig;
+use crate::transaction::TransactionType;
+
+/// Runtime configuration of the bank, loaded from a JSON file.
+/// Every field is optional and falls back to its default value.
+#[derive(Deserialize)]
+#[derive(Debug)]
+#[serde(default)]
+pub struct Config {
+    /// The currency of the transactions that do not specify one.
+    pub default_currency: String,
+
+    /// The policy applied to every account without an override.
+    pub balance_policy: BalancePolicy,
+
+    /// Policies overriding the global one, indexed by customer ID.
+    pub client_balance_policies: HashMap<u16, BalancePolicy>,
+
+    /// The precision applied to every currency without an override.
+    pub precision: Precision,
+
+    /// Precisions overriding the global one, indexed by currency.
+    pub currency_precisions: HashMap<String, Precision>,
+
+    pub fees: FeeSchedule,
+
+    /// How the disputes of a transfer are handled.
+    pub transfer_disputes: TransferDisputePolicy,
+
+    /// The number of rows processed after which an authorization that was
+    /// not captured or voided expires. Authorizations never expire by default.
+    pub authorization_expiry_rows: Option<u64>,
+
+    /// The number of seconds after which an authorization that was not
+    /// captured or voided expires. Only applies to timestamped transactions.
+    pub authorization_expiry_seconds: Option<i64>,
+
+    /// The number of seconds after a transaction during which it can be
+    /// disputed. Transactions can be disputed at any time by default.
+    pub dispute_window_seconds: Option<i64>,
+
+    /// The limits applied to every account without an override.
+    pub limits: Limits,
+
+    /// Limits overriding the global ones, indexed by customer ID.
+    pub client_limits: HashMap<u16, Limits>,
+
+    /// The fraud rules watching the transactions. Every rule is disabled by default.
+    pub fraud: FraudRules,
+
+    /// The interest rates applied when accruing interest.
+    pub interest: InterestSchedule,
+
+    pub log: LogConfig,
+
+    /// Whether the malformed rows of the input are logged and skipped. By
+    /// default, a malformed row stops the processing with an error.
+    pub skip_malformed_rows: bool,
+}
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            default_currency: crate::consts::DEFAULT_CURRENCY.to_string(),
+            balance_policy: BalancePolicy::default(),
+            client_balance_policies: HashMap::new(),
+            precision: Precision::default(),
+            currency_precisions: HashMap::new(),
+            fees: FeeSchedule::default(),
+            transfer_disputes: TransferDisputePolicy::default(),
+            authorization_expiry_rows: None,
+            authorization_expiry_seconds: None,
+            dispute_window_seconds: None,
+            limits: Limits::default(),
+            client_limits: HashMap::new(),
+            fraud: FraudRules::default(),
+            interest: InterestSchedule::default(),
+            log: LogConfig::default(),
+            skip_malformed_rows: false,
+        }
+    }
+}
+impl Config {
+    pub fn from_file(config_file_path: &str) -> Result<Config, Box<dyn Error>> {
+        let content = fs::read_to_string(config_file_path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn get_balance_policy(&self, client_id: u16) -> &BalancePolicy {
+        match self.client_balance_policies.get(&client_id) {
+            Some(policy) => policy,
+            None => &self.balance_policy,
+        }
+    }
+
+    pub fn get_limits(&self, client_id: u16) -> &Limits {
+        match self.client_limits.get(&client_id) {
+            Some(limits) => limits,
+            None => &self.limits,
+        }
+    }
+
+    pub fn get_precision(&self, currency: &str) -> &Precision {
+        match self
+            .currency_precisions
+            .iter()
+            .find(|(c, _)| c.eq_ignore_ascii_case(currency))
+        {
+            Some((_, precision)) => precision,
+            None => &self.precision,
+        }
+    }
+}
+
+/// Determines how far the available funds of an account can go below zero,
+/// either through a withdrawal or through a dispute.
+#[derive(Deserialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+#[derive(Default)]
+#[serde(rename_all = "snake_case")]
+pub enum BalancePolicy {
+    /// The available funds can never be negative.
+    #[default]
+    Strict,
+    /// The available funds can go down to the negative of the limit.
+    Overdraft { limit: Decimal },
+    /// The available funds can be negative, but the account gets flagged.
+    AllowAndFlag,
+}
+/// The number of decimal digits of the amounts in a currency, and how
+/// the amounts with more digits are handled.
+#[derive(Deserialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+#[serde(default)]
+pub struct Precision {
+    pub decimals: u32,
+
+    pub rounding: Rounding,
+}
+impl Default for Precision {
+    fn default() -> Precision {
+        Precision {
+            decimals: crate::consts::DECIMAL_PRECISION,
+            rounding: Rounding::default(),
+        }
+    }
+}
+impl Precision {
+    /// Rounds an input amount, or rejects it if it has too many digits
+    /// and the rounding mode is `Reject`.
+    pub fn validate(&self, amount: Decimal) -> Result<Decimal, TransactionError> {
+        if self.rounding == Rounding::Reject && amount.normalize().scale() > self.decimals {
+            return Err(TransactionError::ExcessPrecision {
+                amount,
+                decimals: self.decimals,
+            });
+        }
+        Ok(self.round(amount))
+    }
+
+    /// Rounds an amount for output. Amounts are never rejected at this
+    /// point, so the `Reject` mode rounds half to even.
+    pub fn round(&self, amount: Decimal) -> Decimal {
+        let strategy = match self.rounding {
+            Rounding::HalfUp => RoundingStrategy::MidpointAwayFromZero,
+            Rounding::HalfEven | Rounding::Reject => RoundingStrategy::MidpointNearestEven,
+            Rounding::Truncate => RoundingStrategy::ToZero,
+        };
+        amount.round_dp_with_strategy(self.decimals, strategy)
+    }
+}
+
+#[derive(Deserialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+#[derive(Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Rounding {
+    HalfUp,
+    /// Also known as banker's rounding.
+    #[default]
+    HalfEven,
+    Truncate,
+    /// Inputs with more digits than the precision are rejected.
+    Reject,
+}
+
+/// The fees charged for each type of transaction. Fees are charged in the
+/// currency of the transaction, from the available funds of the account.
+#[derive(Deserialize)]
+#[derive(Debug)]
+#[derive(Default)]
+#[serde(default)]
+pub struct FeeSchedule {
+    pub deposit: Option<Fee>,
+
+    pub withdrawal: Option<Fee>,
+
+    /// Charged to the source account of the transfer.
+    pub transfer: Option<Fee>,
+
+    /// What happens to the fees of a transaction that was charged back.
+    pub chargeback: FeeRefundPolicy,
+}
+impl FeeSchedule {
+    pub fn get_fee(&self, transaction_type: &TransactionType) -> Option<&Fee> {
+        match transaction_type {
+            TransactionType::Deposit => self.deposit.as_ref(),
+            TransactionType::Withdrawal => self.withdrawal.as_ref(),
+            TransactionType::Transfer => self.transfer.as_ref(),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Fee {
+    Flat {
+        amount: Decimal,
+    },
+    /// A percentage of the amount of the transaction.
+    Percentage {
+        percent: Decimal,
+    },
+    /// The fee of the first tier that covers the amount of the transaction.
+    Tiered {
+        tiers: Vec<FeeTier>,
+    },
+}
+impl Fee {
+    /// Computes the fee for a transaction amount, before rounding.
+    pub fn compute(&self, amount: Decimal) -> Decimal {
+        match self {
+            Fee::Flat { amount: fee } => *fee,
+            Fee::Percentage { percent } => amount * *percent / Decimal::ONE_HUNDRED,
+            Fee::Tiered { tiers } => {
+                match tiers
+                    .iter()
+                    .find(|tier| tier.up_to.is_none_or(|up_to| amount <= up_to))
+                {
+                    Some(tier) => tier.fee.compute(amount),
+                    None => Decimal::ZERO,
+                }
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub struct FeeTier {
+    /// The highest amount covered by the tier. The last tier
+    /// usually has no upper bound.
+    pub up_to: Option<Decimal>,
+
+    pub fee: Fee,
+}
+
+#[derive(Deserialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+#[derive(Default)]
+#[serde(rename_all = "snake_case")]
+pub enum FeeRefundPolicy {
+    /// The bank keeps the fees.
+    #[default]
+    Keep,
+    /// The fees are refunded to the customer.
+    Refund,
+}
+
+#[derive(Deserialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+#[derive(Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferDisputePolicy {
+    /// Transfers cannot be disputed.
+    #[default]
+    Reject,
+    /// A transfer is disputed by the source customer as a withdrawal.
+    /// The funds are held in the source account, and the destination
+    /// account is not affected.
+    Source,
+}
+
+/// Limits on the funds debited from an account. The limits are based
+/// on the timestamps of the transactions, so transactions processed before
+/// the first timestamp of an account are not limited.
+#[derive(Deserialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+#[serde(default)]
+pub struct Limits {
+    /// The highest number of withdrawals, transfers and captures during
+    /// any window of `window_seconds`, in all currencies.
+    pub max_withdrawals: Option<u32>,
+
+    /// The length of the window of `max_withdrawals`, one day by default.
+    pub window_seconds: i64,
+
+    /// The highest amount that can be withdrawn, transferred or captured
+    /// during a calendar day (UTC), in each currency.
+    pub daily_withdrawal_amount: Option<Decimal>,
+}
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_withdrawals: None,
+            window_seconds: 86400,
+            daily_withdrawal_amount: None,
+        }
+    }
+}
+
+/// The interest rates of the available funds, per accrual period. The rate
+/// of the first tier covering the available funds applies to all of them.
+#[derive(Deserialize)]
+#[derive(Debug)]
+#[derive(Default)]
+#[serde(default)]
+pub struct InterestSchedule {
+    /// The tiers of every currency without an override. No interest
+    /// is accrued by default.
+    pub tiers: Vec<InterestTier>,
+
+    /// Tiers overriding the global ones, indexed by currency.
+    pub currency_tiers: HashMap<String, Vec<InterestTier>>,
+}
+impl InterestSchedule {
+    /// Gets the interest rate of available funds in a currency, in percent.
+    pub fn get_rate(&self, currency: &str, available: Decimal) -> Decimal {
+        let tiers = match self
+            .currency_tiers
+            .iter()
+            .find(|(c, _)| c.eq_ignore_ascii_case(currency))
+        {
+            Some((_, tiers)) => tiers,
+            None => &self.tiers,
+        };
+        match tiers
+            .iter()
+            .find(|tier| tier.up_to.is_none_or(|up_to| available <= up_to))
+        {
+            Some(tier) => tier.percent,
+            None => Decimal::ZERO,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub struct InterestTier {
+    /// The highest available funds covered by the tier. The last tier
+    /// usually has no upper bound.
+    pub up_to: Option<Decimal>,
+
+    pub percent: Decimal,
+}
+
+#[derive(Deserialize)]
+#[derive(Debug)]
+#[derive(Default)]
+#[serde(default)]
+pub struct FraudRules {
+    pub dispute_burst: Option<DisputeBurstRule>,
+
+    pub rapid_withdrawal: Option<RapidWithdrawalRule>,
+
+    pub insufficient_funds: Option<InsufficientFundsRule>,
+}
+
+/// Flags the accounts with at least `disputes` disputes among
+/// their last `transactions` transactions.
+#[derive(Deserialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+#[serde(default)]
+pub struct DisputeBurstRule {
+    pub disputes: u32,
+
+    pub transactions: u32,
+
+    /// Whether the flagged accounts are put on hold.
+    pub hold: bool,
+}
+impl Default for DisputeBurstRule {
+    fn default() -> DisputeBurstRule {
+        DisputeBurstRule {
+            disputes: 3,
+            transactions: 10,
+            hold: false,
+        }
+    }
+}
+
+/// Flags the accounts withdrawing or transferring at least `percent`
+/// of a deposit made during their last `transactions` transactions.
+#[derive(Deserialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+#[serde(default)]
+pub struct RapidWithdrawalRule {
+    pub transactions: u32,
+
+    pub percent: Decimal,
+
+    /// Whether the flagged accounts are put on hold.
+    pub hold: bool,
+}
+impl Default for RapidWithdrawalRule {
+    fn default() -> RapidWithdrawalRule {
+        RapidWithdrawalRule {
+            transactions: 1,
+            percent: Decimal::from(90),
+            hold: false,
+        }
+    }
+}
+
+/// Flags the accounts with at least `attempts` debits rejected for
+/// insufficient funds among their last `transactions` transactions.
+#[derive(Deserialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+#[serde(default)]
+pub struct InsufficientFundsRule {
+    pub attempts: u32,
+
+    pub transactions: u32,
+
+    /// Whether the flagged accounts are put on hold.
+    pub hold: bool,
+}
+impl Default for InsufficientFundsRule {
+    fn default() -> InsufficientFundsRule {
+        InsufficientFundsRule {
+            attempts: 3,
+            transactions: 10,
+            hold: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_parse_empty_config() {
+        let config: Config = serde_json::from_str("{}").unwrap();
+        assert_eq!(config.default_currency, "USD");
+        assert_eq!(config.balance_policy, BalancePolicy::Strict);
+        assert!(config.client_balance_policies.is_empty());
+        assert_eq!(config.limits, Limits::default());
+    }
+
+    #[test]
+    pub fn test_parse_time_rules() {
+        let config: Config = serde_json::from_str(
+            r#"{
+                "authorization_expiry_seconds": 604800,
+                "dispute_window_seconds": 10368000,
+                "limits": {"daily_withdrawal_amount": "500.0"}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(config.authorization_expiry_seconds, Some(604800));
+        assert_eq!(config.dispute_window_seconds, Some(10368000));
+        assert_eq!(
+            config.limits.daily_withdrawal_amount,
+            Some(Decimal::from_str("500.0").unwrap())
+        );
+    }
+
+    #[test]
+    pub fn test_parse_limits() {
+        let config: Config = serde_json::from_str(
+            r#"{
+                "limits": {"max_withdrawals": 5},
+                "client_limits": {"2": {"max_withdrawals": 1, "window_seconds": 3600}}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            *config.get_limits(1),
+            Limits {
+                max_withdrawals: Some(5),
+                window_seconds: 86400,
+                daily_withdrawal_amount: None,
+            }
+        );
+        assert_eq!(config.get_limits(2).max_withdrawals, Some(1));
+        assert_eq!(config.get_limits(2).window_seconds, 3600);
+    }
+
+    #[test]
+    pub fn test_parse_fraud_rules() {
+        let config: Config = serde_json::from_str(
+            r#"{
+                "fraud": {
+                    "dispute_burst": {"disputes": 2, "hold": true},
+                    "rapid_withdrawal": {}
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.fraud.dispute_burst,
+            Some(DisputeBurstRule {
+                disputes: 2,
+                transactions: 10,
+                hold: true,
+            })
+        );
+        assert_eq!(
+            config.fraud.rapid_withdrawal,
+            Some(RapidWithdrawalRule::default())
+        );
+        assert_eq!(config.fraud.insufficient_funds, None);
+    }
+
+    #[test]
+    pub fn test_parse_balance_policies() {
+        let config: Config = serde_json::from_str(
+            r#"{
+                "balance_policy": {"overdraft": {"limit": "50.0"}},
+                "client_balance_policies": {"2": "allow_and_flag"}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            *config.get_balance_policy(1),
+            BalancePolicy::Overdraft {
+                limit: Decimal::from_str("50.0").unwrap()
+            }
+        );
+        assert_eq!(*config.get_balance_policy(2), BalancePolicy::AllowAndFlag);
+    }
+
+    #[test]
+    pub fn test_parse_precisions() {
+        let config: Config = serde_json::from_str(
+            r#"{
+                "precision": {"rounding": "half_up"},
+                "currency_precisions": {"jpy": {"decimals": 0, "rounding": "truncate"}}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            *config.get_precision("USD"),
+            Precision {
+                decimals: 4,
+                rounding: Rounding::HalfUp
+            }
+        );
+        assert_eq!(
+            *config.get_precision("JPY"),
+            Precision {
+                decimals: 0,
+                rounding: Rounding::Truncate
+            }
+        );
+    }
+
+    #[test]
+    pub fn test_parse_fees() {
+        let config: Config = serde_json::from_str(
+            r#"{
+                "fees": {
+                    "deposit": {"flat": {"amount": "0.5"}},
+                    "withdrawal": {"tiered": {"tiers": [
+                        {"up_to": "100", "fee": {"flat": {"amount": "1"}}},
+                        {"fee": {"percentage": {"percent": "1.5"}}}
+                    ]}},
+                    "chargeback": "refund"
+                }
+            }"#,
+        )
+        .unwrap();
+        let deposit_fee = config.fees.get_fee(&TransactionType::Deposit).unwrap();
+        assert_eq!(
+            deposit_fee.compute(Decimal::from(1000)),
+            Decimal::from_str("0.5").unwrap()
+        );
+        let withdrawal_fee = config.fees.get_fee(&TransactionType::Withdrawal).unwrap();
+        assert_eq!(withdrawal_fee.compute(Decimal::from(100)), Decimal::from(1));
+        assert_eq!(withdrawal_fee.compute(Decimal::from(1000)), Decimal::from(15));
+        assert_eq!(config.fees.get_fee(&TransactionType::Dispute), None);
+        assert_eq!(config.fees.chargeback, FeeRefundPolicy::Refund);
+    }
+
+    #[test]
+    pub fn test_parse_interest() {
+        let config: Config = serde_json::from_str(
+            r#"{
+                "interest": {
+                    "tiers": [{"up_to": "1000", "percent": "0.1"}, {"percent": "0.2"}],
+                    "currency_tiers": {"eur": [{"percent": "0.05"}]}
+                }
+            }"#,
+        )
+        .unwrap();
+        let rate = |currency, available| config.interest.get_rate(currency, Decimal::from(available));
+        assert_eq!(rate("USD", 1000), Decimal::from_str("0.1").unwrap());
+        assert_eq!(rate("USD", 1001), Decimal::from_str("0.2").unwrap());
+        assert_eq!(rate("EUR", 1001), Decimal::from_str("0.05").unwrap());
+        assert_eq!(
+            Config::default().interest.get_rate("USD", Decimal::ONE),
+            Decimal::ZERO
+        );
+    }
+
+    #[test]
+    pub fn test_rounding() {
+        let amount = Decimal::from_str("2.125").unwrap();
+        let mut precision = Precision {
+            decimals: 2,
+            rounding: Rounding::HalfUp,
+        };
+        assert_eq!(precision.validate(amount), Ok(Decimal::from_str("2.13").unwrap()));
+        precision.rounding = Rounding::HalfEven;
+        assert_eq!(precision.validate(amount), Ok(Decimal::from_str("2.12").unwrap()));
+        precision.rounding = Rounding::Truncate;
+        assert_eq!(
+            precision.validate(Decimal::from_str("2.129").unwrap()),
+            Ok(Decimal::from_str("2.12").unwrap())
+        );
+        precision.rounding = Rounding::Reject;
+        assert_eq!(
+            precision.validate(amount),
+            Err(TransactionError::ExcessPrecision { amount, decimals: 2 })
+        );
+        assert_eq!(
+            precision.validate(Decimal::from_str("2.1200").unwrap()),
+            Ok(Decimal::from_str("2.12").unwrap())
+        );
+    }
+}
@@ -1 +1,5 @@
 pub const DECIMAL_PRECISION: u32 = 4;
+pub const DEFAULT_CURRENCY: &str = "USD";
+/// The transaction IDs from this one are reserved for the transactions
+/// generated by the bank, like interest credits.
+pub const SYNTHETIC_TRANSACTION_ID_START: u32 = 0xF000_0000;
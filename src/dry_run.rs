@@ -0,0 +1,225 @@
+use rust_decimal::prelude::*;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+
+use crate::bank::Bank;
+use crate::observer::{Event, MemorySink};
+use crate::snapshot::AccountRow;
+use crate::trail::{Decision, TrailEntry};
+
+/// The change of the funds of an account in a single currency.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub struct Delta {
+    pub client_id: u16,
+
+    pub currency: String,
+
+    pub available: Decimal,
+
+    pub held: Decimal,
+
+    pub reserved: Decimal,
+
+    pub total: Decimal,
+
+    /// The new lock state of the account, if it changed.
+    pub locked: Option<bool>,
+}
+impl fmt::Display for Delta {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut changes: Vec<String> = [
+            ("available", self.available),
+            ("held", self.held),
+            ("reserved", self.reserved),
+            ("total", self.total),
+        ]
+        .iter()
+        .filter(|(_, amount)| !amount.is_zero())
+        .map(|(balance, amount)| {
+            let sign = if amount.is_sign_negative() { "" } else { "+" };
+            format!("{} {}{}", balance, sign, amount.normalize())
+        })
+        .collect();
+        match self.locked {
+            Some(true) => changes.push("locked".to_string()),
+            Some(false) => changes.push("unlocked".to_string()),
+            None => (),
+        }
+        write!(
+            f,
+            "client {} {}: {}",
+            self.client_id,
+            self.currency,
+            changes.join(", ")
+        )
+    }
+}
+
+/// The effect the transactions of a file would have on a bank.
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct DryRunReport {
+    /// The changes of the accounts, ordered by customer ID and currency.
+    pub deltas: Vec<Delta>,
+
+    /// The transactions that would be rejected, in order.
+    pub rejections: Vec<TrailEntry>,
+
+    /// The number of malformed rows that would be skipped.
+    pub malformed_rows: u64,
+}
+impl fmt::Display for DryRunReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} accounts would change:", self.deltas.len())?;
+        for delta in &self.deltas {
+            writeln!(f, "  {}", delta)?;
+        }
+        writeln!(f, "{} transactions would be rejected:", self.rejections.len())?;
+        for rejection in &self.rejections {
+            writeln!(f, "  {}", rejection)?;
+        }
+        if self.malformed_rows > 0 {
+            writeln!(f, "{} malformed rows would be skipped.", self.malformed_rows)?;
+        }
+        Ok(())
+    }
+}
+
+/// Processes the transactions of a file on a scratch copy of the bank, and
+/// reports the changes they would cause and the transactions that would be
+/// rejected. The bank itself is left unchanged.
+pub fn dry_run(bank: &Bank, transactions_file_path: &str) -> Result<DryRunReport, Box<dyn Error>> {
+    let mut scratch = bank.scratch_copy();
+    let sink = MemorySink::new();
+    scratch.add_observer(sink.clone());
+    scratch.process_transactions(transactions_file_path)?;
+    Ok(DryRunReport {
+        deltas: get_deltas(&bank.get_rows(), &scratch.get_rows()),
+        rejections: get_rejections(&sink.get_events()),
+        malformed_rows: scratch.get_malformed_rows() - bank.get_malformed_rows(),
+    })
+}
+
+fn get_deltas(before: &[AccountRow], after: &[AccountRow]) -> Vec<Delta> {
+    let before: BTreeMap<(u16, &str), &AccountRow> = before
+        .iter()
+        .map(|row| ((row.client, row.currency.as_str()), row))
+        .collect();
+    let mut deltas = vec![];
+    for row in after {
+        let delta = match before.get(&(row.client, row.currency.as_str())) {
+            Some(previous) => Delta {
+                client_id: row.client,
+                currency: row.currency.clone(),
+                available: row.available - previous.available,
+                held: row.held - previous.held,
+                reserved: row.reserved - previous.reserved,
+                total: row.total - previous.total,
+                locked: Some(row.locked).filter(|locked| *locked != previous.locked),
+            },
+            None => Delta {
+                client_id: row.client,
+                currency: row.currency.clone(),
+                available: row.available,
+                held: row.held,
+                reserved: row.reserved,
+                total: row.total,
+                locked: Some(row.locked).filter(|locked| *locked),
+            },
+        };
+        let amounts = [delta.available, delta.held, delta.reserved, delta.total];
+        if delta.locked.is_some() || amounts.iter().any(|amount| !amount.is_zero()) {
+            deltas.push(delta);
+        }
+    }
+    deltas
+}
+
+fn get_rejections(events: &[Event]) -> Vec<TrailEntry> {
+    let mut rejections = vec![];
+    let mut received = None;
+    for event in events {
+        match event {
+            Event::Received {
+                row,
+                line,
+                client_id,
+                transaction_id,
+                r#type,
+                destination,
+                ..
+            } => received = Some((*row, *line, *client_id, *transaction_id, r#type, *destination)),
+            Event::Rejected { reason, message, .. } => {
+                if let Some((row, line, client_id, transaction_id, r#type, destination)) = received.take() {
+                    rejections.push(TrailEntry {
+                        row,
+                        line,
+                        client_id,
+                        transaction_id,
+                        r#type: r#type.clone(),
+                        destination,
+                        decision: Decision::Rejected {
+                            reason: reason.clone(),
+                            message: message.clone(),
+                        },
+                    });
+                }
+            }
+            _ => (),
+        }
+    }
+    rejections
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::transaction;
+    use crate::transaction::TransactionType;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    pub fn test_dry_run() {
+        let mut bank = Bank::new();
+        bank.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some("10.0")));
+        bank.process_transaction(transaction(TransactionType::Deposit, 2, 2, Some("5.0")));
+
+        let path = env::temp_dir().join(format!("tx-processor-dry-run-{}.csv", std::process::id()));
+        fs::write(
+            &path,
+            "type,client,tx,amount\n\
+             withdrawal,1,3,4.0\n\
+             dispute,2,2,\n\
+             chargeback,2,2,\n\
+             withdrawal,3,4,1.0\n\
+             deposit,3,5,2.5\n",
+        )
+        .unwrap();
+        let report = dry_run(&bank, path.to_str().unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let deltas: Vec<String> = report.deltas.iter().map(|delta| delta.to_string()).collect();
+        assert_eq!(
+            deltas,
+            vec![
+                "client 1 USD: available -4, total -4",
+                "client 2 USD: available -5, total -5, locked",
+                "client 3 USD: available +2.5, total +2.5",
+            ]
+        );
+        assert_eq!(report.rejections.len(), 1);
+        assert_eq!(report.rejections[0].transaction_id, 4);
+
+        // The bank itself is unchanged.
+        assert_eq!(
+            bank.accounts[&1].get_balance("USD").available,
+            Decimal::from_str("10.0").unwrap()
+        );
+        assert!(!bank.accounts[&2].is_locked());
+        assert!(!bank.accounts.contains_key(&3));
+    }
+}
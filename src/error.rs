@@ -0,0 +1,208 @@
+use rust_decimal::prelude::*;
+use std::error::Error;
+use std::fmt;
+
+/// The reason why a transaction was rejected by an account.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub enum TransactionError {
+    AccountLocked,
+    /// The transaction ID or the type of the transaction is reserved
+    /// for the transactions generated by the bank.
+    ReservedTransaction(u32),
+    /// No handler is registered for the custom transaction type.
+    UnknownTransactionType(String),
+    /// The account was put on hold by a fraud rule, so it cannot be debited.
+    AccountOnHold,
+    DuplicateTransaction(u32),
+    MissingAmount,
+    InvalidAmount(Decimal),
+    /// The amount has more decimal digits than the precision of its currency.
+    ExcessPrecision {
+        amount: Decimal,
+        decimals: u32,
+    },
+    /// The debit would make the available funds negative.
+    InsufficientFunds {
+        available: Decimal,
+        amount: Decimal,
+    },
+    /// The debit would make the available funds go beyond the overdraft limit.
+    OverdraftLimitExceeded {
+        available: Decimal,
+        amount: Decimal,
+        limit: Decimal,
+    },
+    UnknownTransaction(u32),
+    NotDisputable(u32),
+    AlreadyDisputed(u32),
+    NotDisputed(u32),
+    /// The amount of a dispute, a resolve or a chargeback is higher than the
+    /// amount that can still be disputed, resolved or charged back.
+    ExcessDisputeAmount {
+        amount: Decimal,
+        remaining: Decimal,
+    },
+    /// The transaction cannot be reversed or refunded.
+    NotReversible(u32),
+    AlreadyReversed(u32),
+    /// The transaction cannot be reversed or refunded while it is disputed.
+    UnderDispute(u32),
+    /// The transaction does not reference an authorization.
+    UnknownAuthorization(u32),
+    /// The authorization was already captured, voided, or it expired.
+    AuthorizationClosed(u32),
+    MissingDestination,
+    /// The destination of a transfer is the source account.
+    InvalidDestination(u16),
+    DestinationLocked(u16),
+    /// The timestamp of the transaction is earlier than the timestamp
+    /// of the last transaction of the account.
+    TimestampOutOfOrder {
+        timestamp: i64,
+        last: i64,
+    },
+    /// The transaction is older than the dispute window.
+    DisputeWindowExpired(u32),
+    /// The account already made the highest number of withdrawals and
+    /// transfers allowed during the window.
+    WithdrawalCountExceeded {
+        limit: u32,
+        window_seconds: i64,
+    },
+    /// The debit would make the funds withdrawn during the day go beyond the limit.
+    DailyWithdrawalLimitExceeded {
+        withdrawn: Decimal,
+        amount: Decimal,
+        limit: Decimal,
+    },
+}
+impl fmt::Display for TransactionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TransactionError::AccountLocked => write!(f, "The account is locked."),
+            TransactionError::ReservedTransaction(id) => {
+                write!(f, "Transaction {} is reserved for the bank.", id)
+            }
+            TransactionError::UnknownTransactionType(name) => {
+                write!(f, "No handler is registered for transaction type {}.", name)
+            }
+            TransactionError::AccountOnHold => write!(f, "The account is on hold."),
+            TransactionError::DuplicateTransaction(id) => {
+                write!(f, "Transaction {} was already processed.", id)
+            }
+            TransactionError::MissingAmount => write!(f, "The transaction has no amount."),
+            TransactionError::InvalidAmount(amount) => write!(f, "Invalid amount {}.", amount),
+            TransactionError::ExcessPrecision { amount, decimals } => {
+                write!(f, "Amount {} has more than {} decimal digits.", amount, decimals)
+            }
+            TransactionError::InsufficientFunds { available, amount } => write!(
+                f,
+                "Insufficient amount: {} is available, {} was requested.",
+                available, amount
+            ),
+            TransactionError::OverdraftLimitExceeded {
+                available,
+                amount,
+                limit,
+            } => write!(
+                f,
+                "Overdraft limit of {} exceeded: {} is available, {} was requested.",
+                limit, available, amount
+            ),
+            TransactionError::UnknownTransaction(id) => write!(f, "Transaction {} does not exist.", id),
+            TransactionError::NotDisputable(id) => write!(f, "Transaction {} is not disputable.", id),
+            TransactionError::AlreadyDisputed(id) => write!(f, "Transaction {} is already disputed.", id),
+            TransactionError::NotDisputed(id) => write!(f, "Transaction {} is not disputed.", id),
+            TransactionError::ExcessDisputeAmount { amount, remaining } => write!(
+                f,
+                "Amount {} is higher than the remaining amount {}.",
+                amount, remaining
+            ),
+            TransactionError::NotReversible(id) => write!(f, "Transaction {} cannot be reversed.", id),
+            TransactionError::AlreadyReversed(id) => write!(f, "Transaction {} was already reversed.", id),
+            TransactionError::UnderDispute(id) => write!(f, "Transaction {} is under dispute.", id),
+            TransactionError::UnknownAuthorization(id) => {
+                write!(f, "Transaction {} is not an authorization.", id)
+            }
+            TransactionError::AuthorizationClosed(id) => write!(f, "Authorization {} is closed.", id),
+            TransactionError::MissingDestination => write!(f, "The transfer has no destination."),
+            TransactionError::InvalidDestination(id) => {
+                write!(f, "Client {} cannot transfer funds to itself.", id)
+            }
+            TransactionError::DestinationLocked(id) => {
+                write!(f, "The account of the destination client {} is locked.", id)
+            }
+            TransactionError::TimestampOutOfOrder { timestamp, last } => write!(
+                f,
+                "Timestamp {} is earlier than the last timestamp {} of the account.",
+                timestamp, last
+            ),
+            TransactionError::DisputeWindowExpired(id) => {
+                write!(f, "Transaction {} is too old to be disputed.", id)
+            }
+            TransactionError::WithdrawalCountExceeded {
+                limit,
+                window_seconds,
+            } => write!(
+                f,
+                "Limit of {} withdrawals per {} seconds exceeded.",
+                limit, window_seconds
+            ),
+            TransactionError::DailyWithdrawalLimitExceeded {
+                withdrawn,
+                amount,
+                limit,
+            } => write!(
+                f,
+                "Daily withdrawal limit of {} exceeded: {} was already withdrawn, {} was requested.",
+                limit, withdrawn, amount
+            ),
+        }
+    }
+}
+impl TransactionError {
+    /// Gets a short code identifying the reason of the rejection.
+    pub fn get_code(&self) -> &'static str {
+        match self {
+            TransactionError::AccountLocked => "account_locked",
+            TransactionError::ReservedTransaction(_) => "reserved_transaction",
+            TransactionError::UnknownTransactionType(_) => "unknown_transaction_type",
+            TransactionError::AccountOnHold => "account_on_hold",
+            TransactionError::DuplicateTransaction(_) => "duplicate_transaction",
+            TransactionError::MissingAmount => "missing_amount",
+            TransactionError::InvalidAmount(_) => "invalid_amount",
+            TransactionError::ExcessPrecision { .. } => "excess_precision",
+            TransactionError::InsufficientFunds { .. } => "insufficient_funds",
+            TransactionError::OverdraftLimitExceeded { .. } => "overdraft_limit_exceeded",
+            TransactionError::UnknownTransaction(_) => "unknown_transaction",
+            TransactionError::NotDisputable(_) => "not_disputable",
+            TransactionError::AlreadyDisputed(_) => "already_disputed",
+            TransactionError::NotDisputed(_) => "not_disputed",
+            TransactionError::ExcessDisputeAmount { .. } => "excess_dispute_amount",
+            TransactionError::NotReversible(_) => "not_reversible",
+            TransactionError::AlreadyReversed(_) => "already_reversed",
+            TransactionError::UnderDispute(_) => "under_dispute",
+            TransactionError::UnknownAuthorization(_) => "unknown_authorization",
+            TransactionError::AuthorizationClosed(_) => "authorization_closed",
+            TransactionError::MissingDestination => "missing_destination",
+            TransactionError::InvalidDestination(_) => "invalid_destination",
+            TransactionError::DestinationLocked(_) => "destination_locked",
+            TransactionError::TimestampOutOfOrder { .. } => "timestamp_out_of_order",
+            TransactionError::DisputeWindowExpired(_) => "dispute_window_expired",
+            TransactionError::WithdrawalCountExceeded { .. } => "withdrawal_count_exceeded",
+            TransactionError::DailyWithdrawalLimitExceeded { .. } => "daily_withdrawal_limit_exceeded",
+        }
+    }
+
+    /// Whether the transaction was rejected by the limits of the account.
+    pub fn is_limit_violation(&self) -> bool {
+        matches!(
+            self,
+            TransactionError::WithdrawalCountExceeded { .. }
+                | TransactionError::DailyWithdrawalLimitExceeded { .. }
+        )
+    }
+}
+impl Error for TransactionError {}
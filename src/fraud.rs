@@ -0,0 +1,290 @@
+use rust_decimal::prelude::*;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+
+use crate::config::{DisputeBurstRule, FraudRules, InsufficientFundsRule, RapidWithdrawalRule};
+use crate::error::TransactionError;
+use crate::ledger::LedgerEntry;
+use crate::transaction::{Transaction, TransactionType};
+
+/// A heuristic watching the transactions of every customer. Rules keep
+/// their own state for each customer.
+pub trait FraudRule {
+    /// The flag raised on the accounts caught by the rule.
+    fn flag(&self) -> &str;
+
+    /// Whether the accounts caught by the rule are put on hold,
+    /// which rejects their debits.
+    fn holds(&self) -> bool;
+
+    /// Observes a transaction of a customer and the outcome of its processing,
+    /// and returns whether the account of the customer is caught by the rule.
+    fn observe(&mut self, tx: &Transaction, result: &Result<Vec<LedgerEntry>, TransactionError>) -> bool;
+}
+
+/// A flag raised by a rule on the account of a customer.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub struct Alert {
+    pub client_id: u16,
+
+    pub flag: String,
+
+    pub hold: bool,
+}
+
+pub struct FraudEngine {
+    rules: Vec<Box<dyn FraudRule>>,
+}
+impl Default for FraudEngine {
+    fn default() -> FraudEngine {
+        FraudEngine::new()
+    }
+}
+impl FraudEngine {
+    pub fn new() -> FraudEngine {
+        FraudEngine { rules: vec![] }
+    }
+
+    /// Creates an engine with the rules enabled in the configuration.
+    pub fn with_rules(rules: &FraudRules) -> FraudEngine {
+        let mut engine = FraudEngine::new();
+        if let Some(rule) = &rules.dispute_burst {
+            engine.add_rule(Box::new(DisputeBurst::new(rule.clone())));
+        }
+        if let Some(rule) = &rules.rapid_withdrawal {
+            engine.add_rule(Box::new(RapidWithdrawal::new(rule.clone())));
+        }
+        if let Some(rule) = &rules.insufficient_funds {
+            engine.add_rule(Box::new(RepeatedInsufficientFunds::new(rule.clone())));
+        }
+        engine
+    }
+
+    pub fn add_rule(&mut self, rule: Box<dyn FraudRule>) {
+        self.rules.push(rule);
+    }
+
+    /// Feeds a transaction to every rule, and returns the alerts raised.
+    pub fn observe(
+        &mut self,
+        tx: &Transaction,
+        result: &Result<Vec<LedgerEntry>, TransactionError>,
+    ) -> Vec<Alert> {
+        let mut alerts = vec![];
+        for rule in self.rules.iter_mut() {
+            if rule.observe(tx, result) {
+                alerts.push(Alert {
+                    client_id: tx.client_id,
+                    flag: rule.flag().to_string(),
+                    hold: rule.holds(),
+                });
+            }
+        }
+        alerts
+    }
+}
+
+// Appends a value to the window of the last transactions of a customer.
+fn push<T>(window: &mut VecDeque<T>, size: u32, value: T) {
+    window.push_back(value);
+    while window.len() > size as usize {
+        window.pop_front();
+    }
+}
+
+/// Catches the customers disputing many transactions in a short span.
+pub struct DisputeBurst {
+    rule: DisputeBurstRule,
+
+    // Whether each of the last transactions of a customer is a dispute,
+    // indexed by customer ID.
+    windows: HashMap<u16, VecDeque<bool>>,
+}
+impl DisputeBurst {
+    pub fn new(rule: DisputeBurstRule) -> DisputeBurst {
+        DisputeBurst {
+            rule,
+            windows: HashMap::new(),
+        }
+    }
+}
+impl FraudRule for DisputeBurst {
+    fn flag(&self) -> &str {
+        "dispute_burst"
+    }
+
+    fn holds(&self) -> bool {
+        self.rule.hold
+    }
+
+    fn observe(&mut self, tx: &Transaction, _result: &Result<Vec<LedgerEntry>, TransactionError>) -> bool {
+        let window = self.windows.entry(tx.client_id).or_default();
+        let is_dispute = *tx.get_type() == TransactionType::Dispute;
+        push(window, self.rule.transactions, is_dispute);
+        is_dispute && window.iter().filter(|d| **d).count() >= self.rule.disputes as usize
+    }
+}
+
+/// Catches the customers withdrawing most of a deposit right after making it.
+pub struct RapidWithdrawal {
+    rule: RapidWithdrawalRule,
+
+    // The amounts of the deposits among the last transactions
+    // of a customer, indexed by customer ID.
+    windows: HashMap<u16, VecDeque<Option<Decimal>>>,
+}
+impl RapidWithdrawal {
+    pub fn new(rule: RapidWithdrawalRule) -> RapidWithdrawal {
+        RapidWithdrawal {
+            rule,
+            windows: HashMap::new(),
+        }
+    }
+}
+impl FraudRule for RapidWithdrawal {
+    fn flag(&self) -> &str {
+        "rapid_withdrawal"
+    }
+
+    fn holds(&self) -> bool {
+        self.rule.hold
+    }
+
+    fn observe(&mut self, tx: &Transaction, result: &Result<Vec<LedgerEntry>, TransactionError>) -> bool {
+        let (amount, applied, percent) = (tx.amount.unwrap_or_default(), result.is_ok(), self.rule.percent);
+        let window = self.windows.entry(tx.client_id).or_default();
+        let caught = match tx.get_type() {
+            TransactionType::Withdrawal | TransactionType::Transfer if applied => window
+                .iter()
+                .flatten()
+                .any(|deposit| amount * Decimal::ONE_HUNDRED >= *deposit * percent),
+            _ => false,
+        };
+        let deposit = match tx.get_type() {
+            TransactionType::Deposit if applied => Some(amount),
+            _ => None,
+        };
+        push(window, self.rule.transactions, deposit);
+        caught
+    }
+}
+
+/// Catches the customers repeatedly trying to debit more than their funds.
+pub struct RepeatedInsufficientFunds {
+    rule: InsufficientFundsRule,
+
+    // Whether each of the last transactions of a customer was rejected
+    // for insufficient funds, indexed by customer ID.
+    windows: HashMap<u16, VecDeque<bool>>,
+}
+impl RepeatedInsufficientFunds {
+    pub fn new(rule: InsufficientFundsRule) -> RepeatedInsufficientFunds {
+        RepeatedInsufficientFunds {
+            rule,
+            windows: HashMap::new(),
+        }
+    }
+}
+impl FraudRule for RepeatedInsufficientFunds {
+    fn flag(&self) -> &str {
+        "insufficient_funds"
+    }
+
+    fn holds(&self) -> bool {
+        self.rule.hold
+    }
+
+    fn observe(&mut self, tx: &Transaction, result: &Result<Vec<LedgerEntry>, TransactionError>) -> bool {
+        let window = self.windows.entry(tx.client_id).or_default();
+        let rejected = matches!(
+            result,
+            Err(TransactionError::InsufficientFunds { .. })
+                | Err(TransactionError::OverdraftLimitExceeded { .. })
+        );
+        push(window, self.rule.transactions, rejected);
+        rejected && window.iter().filter(|r| **r).count() >= self.rule.attempts as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::transaction;
+
+    fn insufficient_funds() -> Result<Vec<LedgerEntry>, TransactionError> {
+        Err(TransactionError::InsufficientFunds {
+            available: Decimal::ZERO,
+            amount: Decimal::ONE,
+        })
+    }
+
+    #[test]
+    pub fn test_dispute_burst() {
+        let mut rule = DisputeBurst::new(DisputeBurstRule {
+            disputes: 2,
+            transactions: 3,
+            hold: false,
+        });
+        let dispute = transaction(TransactionType::Dispute, 1, 1, None);
+        let deposit = transaction(TransactionType::Deposit, 1, 1, Some("1.0"));
+        assert!(!rule.observe(&dispute, &Ok(vec![])));
+        assert!(!rule.observe(&deposit, &Ok(vec![])));
+        assert!(!rule.observe(&deposit, &Ok(vec![])));
+        assert!(!rule.observe(&dispute, &Ok(vec![])));
+        // Disputes of other customers are not counted.
+        assert!(!rule.observe(&transaction(TransactionType::Dispute, 2, 1, None), &Ok(vec![])));
+        assert!(rule.observe(&dispute, &Ok(vec![])));
+    }
+
+    #[test]
+    pub fn test_rapid_withdrawal() {
+        let mut rule = RapidWithdrawal::new(RapidWithdrawalRule {
+            transactions: 2,
+            percent: Decimal::from(90),
+            hold: false,
+        });
+        let deposit = transaction(TransactionType::Deposit, 1, 1, Some("100.0"));
+        assert!(!rule.observe(&deposit, &Ok(vec![])));
+        assert!(!rule.observe(
+            &transaction(TransactionType::Withdrawal, 1, 1, Some("50.0")),
+            &Ok(vec![])
+        ));
+        assert!(rule.observe(
+            &transaction(TransactionType::Withdrawal, 1, 1, Some("90.0")),
+            &Ok(vec![])
+        ));
+        // The deposit is out of the window.
+        assert!(!rule.observe(
+            &transaction(TransactionType::Withdrawal, 1, 1, Some("90.0")),
+            &Ok(vec![])
+        ));
+        assert!(!rule.observe(&deposit, &insufficient_funds()));
+        assert!(!rule.observe(
+            &transaction(TransactionType::Withdrawal, 1, 1, Some("90.0")),
+            &Ok(vec![])
+        ));
+    }
+
+    #[test]
+    pub fn test_repeated_insufficient_funds() {
+        let mut engine = FraudEngine::new();
+        engine.add_rule(Box::new(RepeatedInsufficientFunds::new(InsufficientFundsRule {
+            attempts: 2,
+            transactions: 5,
+            hold: true,
+        })));
+        let withdrawal = transaction(TransactionType::Withdrawal, 1, 1, Some("10.0"));
+        assert_eq!(engine.observe(&withdrawal, &insufficient_funds()), vec![]);
+        assert_eq!(engine.observe(&withdrawal, &Ok(vec![])), vec![]);
+        assert_eq!(
+            engine.observe(&withdrawal, &insufficient_funds()),
+            vec![Alert {
+                client_id: 1,
+                flag: "insufficient_funds".to_string(),
+                hold: true,
+            }]
+        );
+    }
+}
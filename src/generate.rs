@@ -0,0 +1,503 @@
+use rust_decimal::prelude::*;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::io::Write;
+
+use crate::consts::SYNTHETIC_TRANSACTION_ID_START;
+use crate::random::Random;
+use crate::timestamp::format_timestamp;
+
+// The transaction types the customers can start, in the order of the header.
+const MIX_TYPES: [&str; 3] = ["deposit", "withdrawal", "transfer"];
+
+// The first of the IDs referenced by the rows with bad references, below the
+// IDs reserved for synthetic transactions, and never used by generated rows.
+const UNUSED_TRANSACTION_ID_START: u32 = SYNTHETIC_TRANSACTION_ID_START - 1000;
+
+// The largest number of decimals of the amounts, so that the amounts scaled
+// to integers fit in an i64.
+const MAX_DECIMALS: u32 = 18;
+
+/// The settings of the transaction generator, loaded from a JSON file.
+/// Every field is optional and falls back to its default value.
+#[derive(Deserialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+#[serde(default)]
+pub struct GeneratorConfig {
+    /// The seed of the generator. The same seed and settings always
+    /// produce the same transactions.
+    pub seed: u64,
+
+    /// The number of rows generated, errors included.
+    pub rows: u64,
+
+    /// The number of customers, with IDs starting from 1.
+    pub clients: u16,
+
+    /// The relative weights of the transactions started by the customers,
+    /// indexed by type: `deposit`, `withdrawal` and `transfer`.
+    pub mix: BTreeMap<String, u32>,
+
+    /// The currencies of the transactions. The currency column is
+    /// omitted when empty, so that the default currency is used.
+    pub currencies: Vec<String>,
+
+    pub min_amount: Decimal,
+
+    pub max_amount: Decimal,
+
+    /// The number of decimals of the amounts.
+    pub decimals: u32,
+
+    /// The percentage of rows disputing an earlier deposit or withdrawal,
+    /// and of rows closing an open dispute.
+    pub dispute_percent: f64,
+
+    /// The percentage of the disputes closed with a chargeback, the
+    /// others being resolved.
+    pub chargeback_percent: f64,
+
+    /// The percentage of deposits reusing the ID of an earlier transaction.
+    pub duplicate_percent: f64,
+
+    /// The percentage of disputes, resolves and chargebacks referencing a
+    /// transaction that does not exist or belongs to another customer.
+    pub bad_reference_percent: f64,
+
+    /// The percentage of rows that cannot be read as a transaction.
+    pub malformed_percent: f64,
+
+    /// The timestamp of the first row, in seconds since the Unix epoch. The
+    /// rows are a few seconds apart, and are not timestamped by default.
+    pub start_timestamp: Option<i64>,
+}
+impl Default for GeneratorConfig {
+    fn default() -> GeneratorConfig {
+        GeneratorConfig {
+            seed: 0,
+            rows: 1000,
+            clients: 100,
+            mix: MIX_TYPES
+                .iter()
+                .zip([60, 30, 10])
+                .map(|(r#type, weight)| (r#type.to_string(), weight))
+                .collect(),
+            currencies: vec![],
+            min_amount: Decimal::new(1, 2),
+            max_amount: Decimal::from(1000),
+            decimals: 2,
+            dispute_percent: 2.0,
+            chargeback_percent: 25.0,
+            duplicate_percent: 0.0,
+            bad_reference_percent: 0.0,
+            malformed_percent: 0.0,
+            start_timestamp: None,
+        }
+    }
+}
+impl GeneratorConfig {
+    pub fn from_file(config_file_path: &str) -> Result<GeneratorConfig, Box<dyn Error>> {
+        let content = fs::read_to_string(config_file_path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    // Gets the weights of the transactions started by the customers, in the
+    // order of `MIX_TYPES`, and checks the settings.
+    fn get_weights(&self) -> Result<Vec<u32>, String> {
+        if let Some(r#type) = self
+            .mix
+            .keys()
+            .find(|r#type| !MIX_TYPES.contains(&r#type.as_str()))
+        {
+            return Err(format!("Unsupported transaction type {} in the mix.", r#type));
+        }
+        let weights: Vec<u32> = MIX_TYPES
+            .iter()
+            .map(|r#type| self.mix.get(*r#type).cloned().unwrap_or(0))
+            .collect();
+        if weights.iter().all(|weight| *weight == 0) {
+            return Err("The mix must have at least one transaction type.".to_string());
+        }
+        if self.clients == 0 || (weights[2] > 0 && self.clients < 2) {
+            return Err("Transfers require at least 2 clients, other transactions at least 1.".to_string());
+        }
+        if self.min_amount > self.max_amount || self.min_amount < Decimal::ZERO {
+            return Err(
+                "The amounts must be positive, and the minimum must not exceed the maximum.".to_string(),
+            );
+        }
+        if self.decimals > MAX_DECIMALS {
+            return Err(format!("The amounts can have at most {} decimals.", MAX_DECIMALS));
+        }
+        let scale = Decimal::from(10u64.pow(self.decimals));
+        if self
+            .max_amount
+            .checked_mul(scale)
+            .and_then(|amount| amount.floor().to_i64())
+            .is_none()
+        {
+            return Err(format!(
+                "The maximum amount {} is too large for {} decimals.",
+                self.max_amount, self.decimals
+            ));
+        }
+        if self.rows > UNUSED_TRANSACTION_ID_START as u64 {
+            return Err(format!(
+                "At most {} rows can be generated.",
+                UNUSED_TRANSACTION_ID_START
+            ));
+        }
+        Ok(weights)
+    }
+}
+
+// The state of a generation.
+struct Generator<'a> {
+    config: &'a GeneratorConfig,
+
+    random: Random,
+
+    // The deposits and withdrawals that were not disputed yet,
+    // as pairs of customer and transaction ID.
+    disputable: Vec<(u16, u32)>,
+
+    // The disputes that were not resolved or charged back yet.
+    disputed: Vec<(u16, u32)>,
+
+    next_transaction_id: u32,
+
+    timestamp: Option<i64>,
+}
+impl Generator<'_> {
+    fn get_client_id(&mut self) -> u16 {
+        self.random.below(self.config.clients as u64) as u16 + 1
+    }
+
+    fn get_amount(&mut self) -> Decimal {
+        let config = self.config;
+        self.random
+            .decimal(config.min_amount, config.max_amount, config.decimals)
+    }
+
+    fn get_transaction_id(&mut self) -> u32 {
+        self.next_transaction_id += 1;
+        self.next_transaction_id - 1
+    }
+
+    fn record(
+        &mut self,
+        r#type: &str,
+        client_id: String,
+        transaction_id: String,
+        amount: Option<String>,
+        destination: Option<u16>,
+    ) -> Vec<String> {
+        let mut record = vec![
+            r#type.to_string(),
+            client_id,
+            transaction_id,
+            amount.unwrap_or_default(),
+        ];
+        if !self.config.currencies.is_empty() {
+            let currency = match record[3].is_empty() {
+                true => String::new(),
+                false => self.random.pick(&self.config.currencies).clone(),
+            };
+            record.push(currency);
+        }
+        if self.config.mix.get("transfer").is_some_and(|weight| *weight > 0) {
+            record.push(destination.map(|d| d.to_string()).unwrap_or_default());
+        }
+        if let Some(timestamp) = self.timestamp.as_mut() {
+            *timestamp += self.random.below(60) as i64 + 1;
+            record.push(format_timestamp(*timestamp));
+        }
+        record
+    }
+
+    fn next_record(&mut self, weights: &[u32]) -> Vec<String> {
+        let config = self.config;
+        if self.random.percent(config.malformed_percent) {
+            let (client_id, transaction_id) = (self.get_client_id(), self.get_transaction_id());
+            let amount = self.get_amount();
+            return match self.random.below(3) {
+                0 => self.record(
+                    "deposit",
+                    "-1".to_string(),
+                    transaction_id.to_string(),
+                    Some(amount.to_string()),
+                    None,
+                ),
+                1 => self.record(
+                    "deposit",
+                    client_id.to_string(),
+                    transaction_id.to_string(),
+                    Some("1.2.3".to_string()),
+                    None,
+                ),
+                _ => self.record(
+                    "",
+                    client_id.to_string(),
+                    transaction_id.to_string(),
+                    Some(amount.to_string()),
+                    None,
+                ),
+            };
+        }
+        if !self.disputed.is_empty() && self.random.percent(config.dispute_percent) {
+            let (client_id, transaction_id) = take(&mut self.random, &mut self.disputed);
+            let r#type = match self.random.percent(config.chargeback_percent) {
+                true => "chargeback",
+                false => "resolve",
+            };
+            return self.record(
+                r#type,
+                client_id.to_string(),
+                transaction_id.to_string(),
+                None,
+                None,
+            );
+        }
+        if !self.disputable.is_empty() && self.random.percent(config.dispute_percent) {
+            let (client_id, transaction_id) = take(&mut self.random, &mut self.disputable);
+            self.disputed.push((client_id, transaction_id));
+            return self.record(
+                "dispute",
+                client_id.to_string(),
+                transaction_id.to_string(),
+                None,
+                None,
+            );
+        }
+        if !self.disputable.is_empty() && self.random.percent(config.duplicate_percent) {
+            let (client_id, transaction_id) = *self.random.pick(&self.disputable);
+            let amount = self.get_amount();
+            return self.record(
+                "deposit",
+                client_id.to_string(),
+                transaction_id.to_string(),
+                Some(amount.to_string()),
+                None,
+            );
+        }
+        if self.random.percent(config.bad_reference_percent) {
+            let r#type = *self.random.pick(&["dispute", "resolve", "chargeback"]);
+            let client_id = self.get_client_id();
+            // Either an ID that was never used, or a transaction of another customer.
+            let others: Vec<u32> = self
+                .disputable
+                .iter()
+                .filter(|(c, _)| *c != client_id)
+                .map(|(_, transaction_id)| *transaction_id)
+                .collect();
+            let transaction_id = match others.is_empty() {
+                false if self.random.percent(50.0) => *self.random.pick(&others),
+                _ => UNUSED_TRANSACTION_ID_START + self.random.below(1000) as u32,
+            };
+            return self.record(
+                r#type,
+                client_id.to_string(),
+                transaction_id.to_string(),
+                None,
+                None,
+            );
+        }
+
+        let (client_id, transaction_id) = (self.get_client_id(), self.get_transaction_id());
+        let amount = self.get_amount();
+        let r#type = MIX_TYPES[self.random.pick_weighted(weights)];
+        let destination = match r#type {
+            "transfer" => {
+                let destination = self.random.below(config.clients as u64 - 1) as u16 + 1;
+                Some(if destination >= client_id {
+                    destination + 1
+                } else {
+                    destination
+                })
+            }
+            _ => {
+                self.disputable.push((client_id, transaction_id));
+                None
+            }
+        };
+        self.record(
+            r#type,
+            client_id.to_string(),
+            transaction_id.to_string(),
+            Some(amount.to_string()),
+            destination,
+        )
+    }
+}
+
+// Takes a random item of a list, which must not be empty.
+fn take(random: &mut Random, list: &mut Vec<(u16, u32)>) -> (u16, u32) {
+    let index = random.below(list.len() as u64) as usize;
+    list.swap_remove(index)
+}
+
+/// Generates transactions as CSV, with the columns expected by the bank.
+pub fn generate(config: &GeneratorConfig, writer: impl Write) -> Result<(), Box<dyn Error>> {
+    let weights = config.get_weights()?;
+    let mut generator = Generator {
+        config,
+        random: Random::new(config.seed),
+        disputable: vec![],
+        disputed: vec![],
+        next_transaction_id: 1,
+        timestamp: config.start_timestamp,
+    };
+
+    let mut writer = csv::Writer::from_writer(writer);
+    let mut header = vec!["type", "client", "tx", "amount"];
+    if !config.currencies.is_empty() {
+        header.push("currency");
+    }
+    if weights[2] > 0 {
+        header.push("destination");
+    }
+    if config.start_timestamp.is_some() {
+        header.push("timestamp");
+    }
+    writer.write_record(&header)?;
+    for _ in 0..config.rows {
+        writer.write_record(generator.next_record(&weights))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank::Bank;
+    use crate::config::Config;
+    use crate::summary::SummaryCollector;
+    use crate::trail::{Decision, TrailCollector, TrailEntry};
+    use crate::transaction::TransactionType;
+    use std::collections::HashSet;
+    use std::env;
+
+    #[test]
+    pub fn test_generate() {
+        let config = GeneratorConfig {
+            seed: 7,
+            rows: 5000,
+            clients: 20,
+            currencies: vec!["USD".to_string(), "EUR".to_string()],
+            dispute_percent: 5.0,
+            duplicate_percent: 1.0,
+            bad_reference_percent: 1.0,
+            malformed_percent: 1.0,
+            start_timestamp: Some(1_650_000_000),
+            ..GeneratorConfig::default()
+        };
+        let mut output = vec![];
+        generate(&config, &mut output).unwrap();
+        let mut other = vec![];
+        generate(&config, &mut other).unwrap();
+        assert_eq!(output, other);
+        let content = String::from_utf8(output).unwrap();
+        assert_eq!(content.lines().count(), 5001);
+        assert!(content.starts_with("type,client,tx,amount,currency,destination,timestamp\n"));
+
+        let path = env::temp_dir().join(format!("tx-processor-generate-{}.csv", std::process::id()));
+        fs::write(&path, &content).unwrap();
+        let mut bank = Bank::with_config(Config {
+            skip_malformed_rows: true,
+            ..Config::default()
+        });
+        let collector = SummaryCollector::new();
+        bank.add_observer(collector.clone());
+        let trail = TrailCollector::new();
+        bank.add_observer(trail.clone());
+        bank.process_transactions(path.to_str().unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let summary = collector.get_summary();
+        assert_eq!(summary.rows_read, 5000);
+        assert!(summary.rows_malformed > 0);
+        assert_eq!(bank.get_malformed_rows(), summary.rows_malformed);
+        assert!(summary.disputes_opened > 0 && summary.chargebacks > 0);
+        for reason in [
+            "duplicate_transaction",
+            "unknown_transaction",
+            "insufficient_funds",
+        ] {
+            assert!(summary.rejected_by_reason.contains_key(reason), "{}", reason);
+        }
+        assert!(!summary.rejected_by_reason.contains_key("reserved_transaction"));
+
+        // The deposits reusing the ID of a transaction are duplicates, rejected by
+        // the account that owns the ID.
+        let mut entries: Vec<TrailEntry> = (1..=config.clients)
+            .flat_map(|client_id| trail.get_trail(client_id))
+            .filter(|entry| [TransactionType::Deposit, TransactionType::Withdrawal].contains(&entry.r#type))
+            .collect();
+        entries.sort_by_key(|entry| entry.row);
+        let mut transaction_ids = HashSet::new();
+        let mut duplicates = 0;
+        for entry in entries {
+            let is_duplicate = transaction_ids.contains(&entry.transaction_id);
+            if let Decision::Applied { .. } = entry.decision {
+                transaction_ids.insert(entry.transaction_id);
+            }
+            if !is_duplicate {
+                continue;
+            }
+            match &entry.decision {
+                Decision::Rejected { reason, .. } => {
+                    assert!(
+                        ["duplicate_transaction", "account_locked"].contains(&reason.as_str()),
+                        "{}",
+                        entry
+                    );
+                    duplicates += 1;
+                }
+                _ => panic!("{}", entry),
+            }
+        }
+        assert!(duplicates > 0);
+
+        // The references to IDs that were never used are unknown to the bank.
+        let bad_references: Vec<TrailEntry> = (1..=config.clients)
+            .flat_map(|client_id| trail.get_trail(client_id))
+            .filter(|entry| entry.transaction_id >= UNUSED_TRANSACTION_ID_START)
+            .collect();
+        assert!(!bad_references.is_empty());
+        for entry in bad_references {
+            match &entry.decision {
+                Decision::Rejected { reason, .. } => {
+                    assert!(
+                        ["unknown_transaction", "account_locked"].contains(&reason.as_str()),
+                        "{}",
+                        entry
+                    )
+                }
+                _ => panic!("{}", entry),
+            }
+        }
+        assert!(bank.audit().is_empty());
+    }
+
+    #[test]
+    pub fn test_invalid_config() {
+        let config: GeneratorConfig = serde_json::from_str(r#"{"mix": {"interest": 1}}"#).unwrap();
+        assert!(generate(&config, vec![]).is_err());
+        let config: GeneratorConfig =
+            serde_json::from_str(r#"{"mix": {"transfer": 1}, "clients": 1}"#).unwrap();
+        assert!(generate(&config, vec![]).is_err());
+        let config: GeneratorConfig = serde_json::from_str(r#"{"mix": {"deposit": 0}}"#).unwrap();
+        assert!(generate(&config, vec![]).is_err());
+        let config: GeneratorConfig = serde_json::from_str(r#"{"decimals": 20}"#).unwrap();
+        assert!(generate(&config, vec![]).is_err());
+        let config: GeneratorConfig = serde_json::from_str(r#"{"decimals": 29}"#).unwrap();
+        assert!(generate(&config, vec![]).is_err());
+        let config: GeneratorConfig =
+            serde_json::from_str(r#"{"decimals": 10, "max_amount": "1000000000000"}"#).unwrap();
+        assert!(generate(&config, vec![]).is_err());
+    }
+}
@@ -0,0 +1,12 @@
+use crate::account::AccountView;
+use crate::error::TransactionError;
+use crate::transaction::Transaction;
+
+/// Processes the transactions of a custom type. Handlers are registered
+/// in the bank under the name of the type they process.
+pub trait TransactionHandler {
+    /// Processes a transaction through a view of the account of its customer.
+    /// If an error is returned, every movement of funds made through the view
+    /// is undone, and the transaction is rejected.
+    fn process(&self, tx: &Transaction, account: &mut AccountView) -> Result<(), TransactionError>;
+}
@@ -0,0 +1,49 @@
+use rust_decimal::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::transaction::TransactionType;
+
+/// A movement of funds recorded in the history of an account.
+/// The balances of an account can always be recomputed by summing
+/// the entries of its history.
+#[derive(Serialize)]
+#[derive(Deserialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub struct LedgerEntry {
+    pub transaction_id: u32,
+
+    /// The type of the transaction that caused the movement.
+    pub r#type: TransactionType,
+
+    pub kind: EntryKind,
+
+    pub currency: String,
+
+    /// The change applied to the available funds.
+    pub available: Decimal,
+
+    /// The change applied to the held funds.
+    pub held: Decimal,
+
+    /// The change applied to the funds reserved by authorizations.
+    pub reserved: Decimal,
+}
+
+#[derive(Serialize)]
+#[derive(Deserialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryKind {
+    /// The movement of funds of the transaction itself.
+    Transaction,
+    /// A fee charged for the transaction, credited to the bank.
+    Fee,
+    /// A fee refunded to the customer, debited from the bank.
+    FeeRefund,
+    /// The release of the funds of an authorization that expired.
+    Expiry,
+}
@@ -0,0 +1,27 @@
+pub mod account;
+pub mod audit;
+pub mod bank;
+pub mod cli;
+pub mod config;
+pub mod consts;
+pub mod dry_run;
+pub mod error;
+pub mod fraud;
+pub mod generate;
+pub mod handler;
+pub mod ledger;
+pub mod log;
+pub mod metrics;
+pub mod observer;
+#[cfg(test)]
+mod properties;
+pub mod random;
+pub mod reconcile;
+pub mod snapshot;
+pub mod summary;
+#[cfg(test)]
+mod test_support;
+pub mod timestamp;
+pub mod trace;
+pub mod trail;
+pub mod transaction;
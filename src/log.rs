@@ -0,0 +1,312 @@
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::timestamp::format_timestamp;
+
+#[derive(Deserialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+#[derive(PartialOrd)]
+#[derive(Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Level {
+    Debug,
+    #[default]
+    Info,
+    Warn,
+    Error,
+}
+impl Level {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            Level::Debug => "debug",
+            Level::Info => "info",
+            Level::Warn => "warn",
+            Level::Error => "error",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+#[derive(Default)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// One line of text per record, with the fields as `key=value` pairs.
+    #[default]
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+/// The configuration of the logs of the bank.
+#[derive(Deserialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+#[serde(default)]
+pub struct LogConfig {
+    /// The records below this level are discarded.
+    pub level: Level,
+
+    pub format: LogFormat,
+
+    /// The file the logs are written to. The logs are written to `stderr` by default.
+    pub file: Option<String>,
+
+    /// The size after which the log file is rotated. The file is never rotated by default.
+    pub max_file_bytes: Option<u64>,
+
+    /// The number of rotated files kept, named after the log file with
+    /// a suffix, from `.1` for the most recent one.
+    pub max_files: u32,
+}
+impl Default for LogConfig {
+    fn default() -> LogConfig {
+        LogConfig {
+            level: Level::default(),
+            format: LogFormat::default(),
+            file: None,
+            max_file_bytes: None,
+            max_files: 5,
+        }
+    }
+}
+
+/// A log file, rotated when it grows beyond its maximum size.
+pub struct RotatingFile {
+    path: String,
+
+    file: File,
+
+    // The current size of the file.
+    size: u64,
+
+    max_bytes: Option<u64>,
+
+    max_files: u32,
+}
+impl RotatingFile {
+    pub fn open(path: &str, max_bytes: Option<u64>, max_files: u32) -> io::Result<RotatingFile> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(RotatingFile {
+            path: path.to_string(),
+            file,
+            size,
+            max_bytes,
+            max_files,
+        })
+    }
+
+    pub fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let length = line.len() as u64 + 1;
+        if self
+            .max_bytes
+            .is_some_and(|max_bytes| self.size > 0 && self.size + length > max_bytes)
+        {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{}", line)?;
+        self.size += length;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for i in (1..self.max_files).rev() {
+                let from = format!("{}.{}", self.path, i);
+                if fs::metadata(&from).is_ok() {
+                    fs::rename(&from, format!("{}.{}", self.path, i + 1))?;
+                }
+            }
+            fs::rename(&self.path, format!("{}.1", self.path))?;
+        }
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+pub enum Destination {
+    Stderr,
+    File(RotatingFile),
+}
+
+pub struct Logger {
+    level: Level,
+
+    format: LogFormat,
+
+    destination: Destination,
+}
+impl Default for Logger {
+    fn default() -> Logger {
+        Logger::new(Level::default(), LogFormat::default(), Destination::Stderr)
+    }
+}
+impl Logger {
+    pub fn new(level: Level, format: LogFormat, destination: Destination) -> Logger {
+        Logger {
+            level,
+            format,
+            destination,
+        }
+    }
+
+    /// Creates the logger described by the configuration. If the log file
+    /// cannot be opened, the logs are written to `stderr`.
+    pub fn with_config(config: &LogConfig) -> Logger {
+        let mut logger = Logger::new(config.level, config.format.clone(), Destination::Stderr);
+        if let Some(path) = &config.file {
+            match RotatingFile::open(path, config.max_file_bytes, config.max_files) {
+                Ok(file) => logger.destination = Destination::File(file),
+                Err(err) => logger.log(
+                    Level::Error,
+                    "Could not open the log file.",
+                    &[
+                        ("file", Value::from(path.as_str())),
+                        ("error", Value::from(err.to_string())),
+                    ],
+                ),
+            };
+        }
+        logger
+    }
+
+    pub fn log(&mut self, level: Level, message: &str, fields: &[(&str, Value)]) {
+        if level < self.level {
+            return;
+        }
+        let timestamp = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(duration) => duration.as_secs() as i64,
+            Err(_) => 0,
+        };
+        let line = format_record(&self.format, timestamp, level, message, fields);
+        match &mut self.destination {
+            Destination::Stderr => eprintln!("{}", line),
+            Destination::File(file) => {
+                if let Err(err) = file.write_line(&line) {
+                    eprintln!("Could not write to the log file: {}", err);
+                    eprintln!("{}", line);
+                }
+            }
+        };
+    }
+
+    pub fn debug(&mut self, message: &str, fields: &[(&str, Value)]) {
+        self.log(Level::Debug, message, fields);
+    }
+
+    pub fn info(&mut self, message: &str, fields: &[(&str, Value)]) {
+        self.log(Level::Info, message, fields);
+    }
+
+    pub fn warn(&mut self, message: &str, fields: &[(&str, Value)]) {
+        self.log(Level::Warn, message, fields);
+    }
+
+    pub fn error(&mut self, message: &str, fields: &[(&str, Value)]) {
+        self.log(Level::Error, message, fields);
+    }
+}
+
+/// Formats a log record as a single line. Null fields are omitted.
+pub fn format_record(
+    format: &LogFormat,
+    timestamp: i64,
+    level: Level,
+    message: &str,
+    fields: &[(&str, Value)],
+) -> String {
+    let fields = fields.iter().filter(|(_, value)| !value.is_null());
+    match format {
+        LogFormat::Text => {
+            let mut line = format!(
+                "{} {} {}",
+                format_timestamp(timestamp),
+                level.get_name().to_uppercase(),
+                message
+            );
+            for (key, value) in fields {
+                let value = match value {
+                    Value::String(s) if !s.is_empty() && !s.contains([' ', '"', '=']) => s.clone(),
+                    _ => value.to_string(),
+                };
+                line.push_str(&format!(" {}={}", key, value));
+            }
+            line
+        }
+        LogFormat::Json => {
+            let mut record = Map::new();
+            record.insert("time".to_string(), Value::from(format_timestamp(timestamp)));
+            record.insert("level".to_string(), Value::from(level.get_name()));
+            record.insert("message".to_string(), Value::from(message));
+            for (key, value) in fields {
+                record.insert(key.to_string(), value.clone());
+            }
+            Value::Object(record).to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn fields() -> Vec<(&'static str, Value)> {
+        vec![
+            ("client", Value::from(1)),
+            ("type", Value::from("withdrawal")),
+            ("line", Value::Null),
+            ("error", Value::from("Insufficient amount.")),
+        ]
+    }
+
+    #[test]
+    pub fn test_format_text() {
+        assert_eq!(
+            format_record(&LogFormat::Text, 0, Level::Warn, "Rejected.", &fields()),
+            r#"1970-01-01T00:00:00Z WARN Rejected. client=1 type=withdrawal error="Insufficient amount.""#
+        );
+    }
+
+    #[test]
+    pub fn test_format_json() {
+        assert_eq!(
+            format_record(&LogFormat::Json, 0, Level::Warn, "Rejected.", &fields()),
+            concat!(
+                r#"{"client":1,"error":"Insufficient amount.","level":"warn","#,
+                r#""message":"Rejected.","time":"1970-01-01T00:00:00Z","type":"withdrawal"}"#
+            )
+        );
+    }
+
+    #[test]
+    pub fn test_rotating_file() {
+        let directory = env::temp_dir().join(format!("tx-processor-log-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("test.log").to_str().unwrap().to_string();
+
+        let mut file = RotatingFile::open(&path, Some(10), 2).unwrap();
+        for line in ["first", "second", "third", "fourth"] {
+            file.write_line(line).unwrap();
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fourth\n");
+        assert_eq!(fs::read_to_string(format!("{}.1", path)).unwrap(), "third\n");
+        assert_eq!(fs::read_to_string(format!("{}.2", path)).unwrap(), "second\n");
+        assert!(fs::metadata(format!("{}.3", path)).is_err());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
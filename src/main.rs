@@ -1,22 +1,289 @@
 use std::env;
+use std::fs;
+use std::io;
+use std::net::TcpListener;
+use std::path::Path;
+use std::process;
+use std::thread;
 
-mod account;
-mod bank;
-mod consts;
-mod transaction;
+use tx_processor::bank;
+use tx_processor::cli::{Command, Options};
+use tx_processor::config;
+use tx_processor::dry_run;
+use tx_processor::generate::{self, GeneratorConfig};
+use tx_processor::metrics::{self, MetricsCollector};
+use tx_processor::observer::JsonlSink;
+use tx_processor::reconcile;
+use tx_processor::snapshot::{self, OutputFormat};
+use tx_processor::summary::SummaryCollector;
+use tx_processor::trace;
+use tx_processor::trail::TrailCollector;
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        panic!("You must provide the path to a transaction file to process.");
+    let options = match Options::parse(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => panic!("{}", err),
+    };
+
+    match &options.command {
+        Command::Process {
+            transactions_file_path,
+        } => process(&options, transactions_file_path, false),
+        Command::AccrueInterest {
+            transactions_file_path,
+        } => process(&options, transactions_file_path, true),
+        Command::Diff {
+            left_file_path,
+            right_file_path,
+        } => diff(&options, left_file_path, right_file_path),
+        Command::Reconcile {
+            transactions_file_path,
+            expected_file_path,
+        } => reconcile(&options, transactions_file_path, expected_file_path),
+        Command::Trace {
+            client_id,
+            transactions_file_path,
+        } => trace(&options, *client_id, transactions_file_path),
+        Command::Generate {
+            settings_file_path,
+            seed,
+            rows,
+        } => generate(settings_file_path.as_deref(), *seed, *rows),
+    }
+}
+
+fn load_config(options: &Options) -> config::Config {
+    let mut config = match &options.config_file_path {
+        Some(p) => match config::Config::from_file(p) {
+            Ok(config) => config,
+            Err(err) => panic!("Could not load the configuration file {}: {}", p, err),
+        },
+        None => config::Config::default(),
+    };
+    config.skip_malformed_rows |= options.skip_malformed;
+    config
+}
+
+// Exits with an error status when malformed rows were skipped.
+fn check_malformed_rows(malformed_rows: u64) {
+    if malformed_rows > 0 {
+        eprintln!("{} malformed rows were skipped.", malformed_rows);
+        process::exit(1);
+    }
+}
+
+// Creates the bank from the state file if it exists, writing its
+// events to the events file if any.
+fn create_bank(options: &Options) -> Result<bank::Bank, String> {
+    let mut bank = bank::Bank::with_config(load_config(options));
+    if let Some(p) = options.state_file_path.as_ref().filter(|p| Path::new(p).exists()) {
+        bank.load_state(p)
+            .map_err(|err| format!("Could not load the state file {}: {}", p, err))?;
+    }
+    if let Some(p) = &options.events_file_path {
+        let sink =
+            JsonlSink::create(p).map_err(|err| format!("Could not create the events file {}: {}", p, err))?;
+        bank.add_observer(sink);
+    }
+    Ok(bank)
+}
+
+// Prints an error and exits with an error status.
+fn exit_with_error(err: String) -> ! {
+    eprintln!("{}", err);
+    process::exit(1);
+}
+
+fn process(options: &Options, transactions_file_path: &str, accrue_interest: bool) {
+    let mut bank = match create_bank(options) {
+        Ok(bank) => bank,
+        Err(err) => exit_with_error(err),
+    };
+    if options.dry_run {
+        match dry_run::dry_run(&bank, transactions_file_path) {
+            Ok(report) => {
+                print!("{}", report);
+                check_malformed_rows(report.malformed_rows);
+            }
+            Err(err) => panic!("Error while processing the transactions: {}", err),
+        }
+        return;
+    }
+
+    let metrics_collector = MetricsCollector::new();
+    let registry = metrics_collector.get_registry();
+    metrics::record_accounts(&mut registry.lock().unwrap(), &bank);
+    bank.add_observer(metrics_collector);
+    let server = options
+        .metrics_address
+        .as_ref()
+        .map(|address| match TcpListener::bind(address) {
+            Ok(listener) => {
+                eprintln!("Serving the metrics on http://{}/metrics", address);
+                let registry = registry.clone();
+                thread::spawn(move || metrics::serve(listener, registry))
+            }
+            Err(err) => panic!("Could not listen on {}: {}", address, err),
+        });
+
+    let collector = SummaryCollector::new();
+    bank.add_observer(collector.clone());
+
+    match &options.as_of {
+        Some(as_of) => match bank.process_transactions_as_of(transactions_file_path, as_of) {
+            Ok(true) => {}
+            Ok(false) => eprintln!("The input ends before {}.", as_of),
+            Err(err) => panic!("Error while processing the transactions: {}", err),
+        },
+        None => {
+            if let Err(err) = bank.process_transactions(transactions_file_path) {
+                panic!("Error while processing the transactions: {}", err);
+            }
+        }
+    }
+    if accrue_interest {
+        if let Err(err) = bank.accrue_interest() {
+            panic!("Could not accrue interest: {}", err);
+        }
+    }
+    metrics::record_accounts(&mut registry.lock().unwrap(), &bank);
+    if let Some(p) = &options.state_file_path {
+        if let Err(err) = bank.save_state(p) {
+            panic!("Could not save the state file {}: {}", p, err);
+        }
+    }
+
+    match options.output_format {
+        OutputFormat::Csv => bank.print(),
+        OutputFormat::Json => bank.print_json(),
+    }
+    bank.print_limit_violations();
+    if let Some(as_of) = &options.as_of {
+        let disputes = bank.get_open_disputes();
+        eprintln!("{} disputes were open as of {}.", disputes.len(), as_of);
+        for dispute in disputes {
+            eprintln!(
+                "Client {}: transaction {} holds {} {}.",
+                dispute.client_id, dispute.transaction_id, dispute.held, dispute.currency
+            );
+        }
     }
-    let transactions_file_path = &args[1];
 
-    let mut bank = bank::Bank::new();
+    let mut summary = collector.get_summary();
+    summary.revenue = bank.revenue.clone();
+    match &options.summary_file_path {
+        Some(p) => {
+            if let Err(err) = summary.write(p) {
+                panic!("Could not write the summary file {}: {}", p, err);
+            }
+        }
+        None => summary.print(),
+    };
 
+    if let Some(p) = &options.metrics_file_path {
+        if let Err(err) = fs::write(p, registry.lock().unwrap().render()) {
+            panic!("Could not write the metrics file {}: {}", p, err);
+        }
+    }
+
+    if options.audit {
+        let issues = bank.audit();
+        for issue in &issues {
+            eprintln!("Audit: {}", issue);
+        }
+        if !issues.is_empty() {
+            process::exit(1);
+        }
+    }
+    check_malformed_rows(bank.get_malformed_rows());
+
+    if let Some(server) = server {
+        if let Ok(Err(err)) = server.join() {
+            panic!("Could not serve the metrics: {}", err);
+        }
+    }
+}
+
+fn diff(options: &Options, left_file_path: &str, right_file_path: &str) {
+    let config = load_config(options);
+    let read = |p: &str| match snapshot::read_snapshot(p) {
+        Ok(rows) => rows,
+        Err(err) => panic!("Could not read the accounts file {}: {}", p, err),
+    };
+    let differences = snapshot::diff(&read(left_file_path), &read(right_file_path), &config);
+    for difference in &differences {
+        println!("{}", difference);
+    }
+    if !differences.is_empty() {
+        process::exit(1);
+    }
+}
+
+fn reconcile(options: &Options, transactions_file_path: &str, expected_file_path: &str) {
+    let expected = match reconcile::read_expected_balances(expected_file_path) {
+        Ok(expected) => expected,
+        Err(err) => panic!(
+            "Could not read the expected balance file {}: {}",
+            expected_file_path, err
+        ),
+    };
+    let mut bank = match create_bank(options) {
+        Ok(bank) => bank,
+        Err(err) => exit_with_error(err),
+    };
+    let trail = TrailCollector::new();
+    bank.add_observer(trail.clone());
+    if let Err(err) = bank.process_transactions(transactions_file_path) {
+        panic!("Error while processing the transactions: {}", err);
+    }
+
+    let breaks = reconcile::reconcile(&bank.get_rows(), &expected, &trail, bank.get_config());
+    for r#break in &breaks {
+        println!("{}", r#break);
+    }
+    if !breaks.is_empty() {
+        eprintln!("{} clients do not match the expected balances.", breaks.len());
+        process::exit(1);
+    }
+    check_malformed_rows(bank.get_malformed_rows());
+}
+
+fn trace(options: &Options, client_id: u16, transactions_file_path: &str) {
+    let mut bank = match create_bank(options) {
+        Ok(bank) => bank,
+        Err(err) => exit_with_error(err),
+    };
+    let balances = match bank.accounts.get(&client_id) {
+        Some(account) => account.get_balances().clone(),
+        None => Default::default(),
+    };
+    let collector = TrailCollector::with_clients(vec![client_id]);
+    bank.add_observer(collector.clone());
     if let Err(err) = bank.process_transactions(transactions_file_path) {
         panic!("Error while processing the transactions: {}", err);
     }
 
-    bank.print()
+    let steps = trace::trace(&balances, collector.get_trail(client_id));
+    if steps.is_empty() {
+        println!("No rows touched client {}.", client_id);
+    }
+    for step in steps {
+        println!("{}", step);
+    }
+    check_malformed_rows(bank.get_malformed_rows());
+}
+
+fn generate(settings_file_path: Option<&str>, seed: Option<u64>, rows: Option<u64>) {
+    let mut config = match settings_file_path {
+        Some(p) => match GeneratorConfig::from_file(p) {
+            Ok(config) => config,
+            Err(err) => panic!("Could not load the settings file {}: {}", p, err),
+        },
+        None => GeneratorConfig::default(),
+    };
+    config.seed = seed.unwrap_or(config.seed);
+    config.rows = rows.unwrap_or(config.rows);
+    if let Err(err) = generate::generate(&config, io::stdout().lock()) {
+        panic!("Could not generate the transactions: {}", err);
+    }
 }
@@ -0,0 +1,417 @@
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rust_decimal::prelude::*;
+
+use crate::bank::Bank;
+use crate::observer::{Event, Observer};
+
+/// The upper bounds of the buckets of the latency histograms, in seconds.
+pub const LATENCY_BUCKETS: [f64; 7] = [0.000_001, 0.000_01, 0.000_1, 0.001, 0.01, 0.1, 1.0];
+
+// The time after which a client of the metrics server that does not send
+// its request, or does not read the response, is disconnected.
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+enum MetricKind {
+    Counter,
+    Gauge,
+    Histogram,
+}
+impl MetricKind {
+    fn get_name(&self) -> &'static str {
+        match self {
+            MetricKind::Counter => "counter",
+            MetricKind::Gauge => "gauge",
+            MetricKind::Histogram => "histogram",
+        }
+    }
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+enum Series {
+    Value(f64),
+    /// The cumulative counts of the buckets, the sum and the count of the observations.
+    Histogram {
+        buckets: Vec<u64>,
+        sum: f64,
+        count: u64,
+    },
+}
+
+#[derive(Debug)]
+struct Metric {
+    help: String,
+
+    kind: MetricKind,
+
+    // The series of the metric, indexed by their rendered labels.
+    series: BTreeMap<String, Series>,
+}
+
+/// A set of metrics, rendered in the Prometheus text format.
+#[derive(Debug)]
+#[derive(Default)]
+pub struct Registry {
+    metrics: BTreeMap<String, Metric>,
+}
+impl Registry {
+    pub fn new() -> Registry {
+        Registry::default()
+    }
+
+    fn get_series(&mut self, name: &str, help: &str, kind: MetricKind, labels: &[(&str, &str)]) -> &mut Series {
+        let metric = self.metrics.entry(name.to_string()).or_insert_with(|| Metric {
+            help: help.to_string(),
+            kind: kind.clone(),
+            series: BTreeMap::new(),
+        });
+        metric
+            .series
+            .entry(render_labels(labels))
+            .or_insert_with(|| match kind {
+                MetricKind::Histogram => Series::Histogram {
+                    buckets: vec![0; LATENCY_BUCKETS.len()],
+                    sum: 0.0,
+                    count: 0,
+                },
+                _ => Series::Value(0.0),
+            })
+    }
+
+    /// Increments a counter.
+    pub fn increment(&mut self, name: &str, help: &str, labels: &[(&str, &str)]) {
+        if let Series::Value(value) = self.get_series(name, help, MetricKind::Counter, labels) {
+            *value += 1.0;
+        }
+    }
+
+    /// Sets the value of a gauge.
+    pub fn set(&mut self, name: &str, help: &str, labels: &[(&str, &str)], value: f64) {
+        if let Series::Value(v) = self.get_series(name, help, MetricKind::Gauge, labels) {
+            *v = value;
+        }
+    }
+
+    /// Adds a value to a gauge, which can be negative.
+    pub fn add(&mut self, name: &str, help: &str, labels: &[(&str, &str)], value: f64) {
+        if let Series::Value(v) = self.get_series(name, help, MetricKind::Gauge, labels) {
+            *v += value;
+        }
+    }
+
+    /// Records an observation in a histogram with the latency buckets.
+    pub fn observe(&mut self, name: &str, help: &str, labels: &[(&str, &str)], value: f64) {
+        if let Series::Histogram { buckets, sum, count } =
+            self.get_series(name, help, MetricKind::Histogram, labels)
+        {
+            for (bucket, bound) in buckets.iter_mut().zip(LATENCY_BUCKETS.iter()) {
+                if value <= *bound {
+                    *bucket += 1;
+                }
+            }
+            *sum += value;
+            *count += 1;
+        }
+    }
+
+    /// Renders the metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+        for (name, metric) in &self.metrics {
+            output.push_str(&format!("# HELP {} {}\n", name, metric.help));
+            output.push_str(&format!("# TYPE {} {}\n", name, metric.kind.get_name()));
+            for (labels, series) in &metric.series {
+                match series {
+                    Series::Value(value) => output.push_str(&format!("{}{} {}\n", name, labels, value)),
+                    Series::Histogram { buckets, sum, count } => {
+                        for (bucket, bound) in buckets.iter().zip(LATENCY_BUCKETS.iter()) {
+                            let labels = add_label(labels, "le", &bound.to_string());
+                            output.push_str(&format!("{}_bucket{} {}\n", name, labels, bucket));
+                        }
+                        let labels_inf = add_label(labels, "le", "+Inf");
+                        output.push_str(&format!("{}_bucket{} {}\n", name, labels_inf, count));
+                        output.push_str(&format!("{}_sum{} {}\n", name, labels, sum));
+                        output.push_str(&format!("{}_count{} {}\n", name, labels, count));
+                    }
+                }
+            }
+        }
+        output
+    }
+}
+
+fn render_labels(labels: &[(&str, &str)]) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+    let labels: Vec<String> = labels
+        .iter()
+        .map(|(key, value)| {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{}=\"{}\"", key, value)
+        })
+        .collect();
+    format!("{{{}}}", labels.join(","))
+}
+
+fn add_label(labels: &str, key: &str, value: &str) -> String {
+    match labels.strip_suffix('}') {
+        Some(labels) => format!("{},{}=\"{}\"}}", labels, key, value),
+        None => render_labels(&[(key, value)]),
+    }
+}
+
+/// Sets the gauges of the accounts from the state of the bank: the number of
+/// accounts, of locked accounts, and the funds held by disputes per currency.
+pub fn record_accounts(registry: &mut Registry, bank: &Bank) {
+    let rows = bank.get_rows();
+    let clients: BTreeSet<u16> = rows.iter().map(|row| row.client).collect();
+    let locked: BTreeSet<u16> = rows
+        .iter()
+        .filter(|row| row.locked)
+        .map(|row| row.client)
+        .collect();
+    registry.set(
+        "tx_processor_accounts",
+        "Accounts handled by the bank.",
+        &[],
+        clients.len() as f64,
+    );
+    registry.set(
+        "tx_processor_locked_accounts",
+        "Accounts locked.",
+        &[],
+        locked.len() as f64,
+    );
+    let mut held: BTreeMap<&str, Decimal> = BTreeMap::new();
+    for row in &rows {
+        *held.entry(&row.currency).or_default() += row.held;
+    }
+    for (currency, amount) in held {
+        // A gauge that cannot be converted is left out rather than reported as zero.
+        match amount.to_f64() {
+            Some(amount) => registry.set(
+                "tx_processor_held_funds",
+                "Funds held by disputes.",
+                &[("currency", currency)],
+                amount,
+            ),
+            None => eprintln!(
+                "Could not convert the funds held in {} to a gauge: {}",
+                currency, amount
+            ),
+        }
+    }
+}
+
+/// Populates a registry from the events of the bank. The gauges of the
+/// accounts are set with `record_accounts`.
+pub struct MetricsCollector {
+    registry: Arc<Mutex<Registry>>,
+
+    // The time at which the transaction being processed was received.
+    received: Option<Instant>,
+}
+impl Default for MetricsCollector {
+    fn default() -> MetricsCollector {
+        MetricsCollector::new()
+    }
+}
+impl MetricsCollector {
+    pub fn new() -> MetricsCollector {
+        MetricsCollector {
+            registry: Arc::new(Mutex::new(Registry::new())),
+            received: None,
+        }
+    }
+
+    /// Gets the registry populated by the collector, which can be
+    /// shared with the thread serving the metrics.
+    pub fn get_registry(&self) -> Arc<Mutex<Registry>> {
+        self.registry.clone()
+    }
+}
+impl Observer for MetricsCollector {
+    fn notify(&mut self, event: &Event) {
+        let mut registry = self.registry.lock().unwrap();
+        let (r#type, outcome) = match event {
+            Event::Malformed { .. } => {
+                registry.increment(
+                    "tx_processor_malformed_rows_total",
+                    "Rows of the input that could not be read.",
+                    &[],
+                );
+                return;
+            }
+            Event::Received { .. } => {
+                self.received = Some(Instant::now());
+                return;
+            }
+            Event::Applied { r#type, .. } => (r#type, "applied"),
+            Event::Rejected { r#type, reason, .. } => {
+                registry.increment(
+                    "tx_processor_rejections_total",
+                    "Transactions rejected, by reason.",
+                    &[("reason", reason)],
+                );
+                (r#type, "rejected")
+            }
+            _ => return,
+        };
+        registry.increment(
+            "tx_processor_transactions_total",
+            "Transactions processed, by type and outcome.",
+            &[("type", r#type.get_name()), ("outcome", outcome)],
+        );
+        if let Some(received) = self.received.take() {
+            registry.observe(
+                "tx_processor_transaction_duration_seconds",
+                "Time spent processing a transaction.",
+                &[],
+                received.elapsed().as_secs_f64(),
+            );
+        }
+    }
+}
+
+/// Serves the metrics over HTTP on `/metrics`, until the listener fails.
+/// Every connection is handled on its own thread, so that a slow client
+/// does not block the others.
+pub fn serve(listener: TcpListener, registry: Arc<Mutex<Registry>>) -> io::Result<()> {
+    for stream in listener.incoming() {
+        let (stream, registry) = (stream?, registry.clone());
+        thread::spawn(move || {
+            if let Err(err) = handle_connection(stream, &registry) {
+                eprintln!("Could not serve the metrics: {}", err);
+            }
+        });
+    }
+    Ok(())
+}
+
+fn handle_connection(mut stream: TcpStream, registry: &Arc<Mutex<Registry>>) -> io::Result<()> {
+    stream.set_read_timeout(Some(CONNECTION_TIMEOUT))?;
+    stream.set_write_timeout(Some(CONNECTION_TIMEOUT))?;
+    let mut request_line = String::new();
+    let mut reader = BufReader::new(stream.try_clone()?);
+    reader.read_line(&mut request_line)?;
+    // The headers of the request are not used.
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let (status, body) = match request_line.split_whitespace().nth(1) {
+        Some("/metrics") => ("200 OK", registry.lock().unwrap().render()),
+        _ => ("404 Not Found", "Not found.\n".to_string()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consts::SYNTHETIC_TRANSACTION_ID_START;
+    use crate::test_support::transaction;
+    use crate::transaction::{Transaction, TransactionType};
+    use std::io::Read;
+
+    #[test]
+    pub fn test_render() {
+        let mut registry = Registry::new();
+        registry.increment("requests_total", "Requests.", &[("path", "/")]);
+        registry.increment("requests_total", "Requests.", &[("path", "/")]);
+        registry.set("temperature", "Temperature.", &[], 21.5);
+        registry.observe("latency_seconds", "Latency.", &[("path", "/")], 0.005);
+        let output = registry.render();
+        assert!(output.contains("# TYPE requests_total counter\nrequests_total{path=\"/\"} 2\n"));
+        assert!(output.contains("# TYPE temperature gauge\ntemperature 21.5\n"));
+        assert!(output.contains("latency_seconds_bucket{path=\"/\",le=\"0.001\"} 0\n"));
+        assert!(output.contains("latency_seconds_bucket{path=\"/\",le=\"0.01\"} 1\n"));
+        assert!(output.contains("latency_seconds_bucket{path=\"/\",le=\"+Inf\"} 1\n"));
+        assert!(output.contains("latency_seconds_count{path=\"/\"} 1\n"));
+    }
+
+    #[test]
+    pub fn test_collector() {
+        let mut bank = Bank::new();
+        let collector = MetricsCollector::new();
+        let registry = collector.get_registry();
+        bank.add_observer(collector);
+        for (r#type, client_id, transaction_id, amount, destination) in [
+            (TransactionType::Deposit, 1, 1, Some("10.0"), None),
+            (TransactionType::Deposit, 1, 2, Some("10.0"), None),
+            (TransactionType::Dispute, 1, 1, None, None),
+            (TransactionType::Transfer, 1, 3, Some("5.0"), Some(4)),
+            (TransactionType::Withdrawal, 2, 1, Some("10.0"), None),
+            (
+                TransactionType::Deposit,
+                3,
+                SYNTHETIC_TRANSACTION_ID_START,
+                Some("10.0"),
+                None,
+            ),
+        ] {
+            bank.process_transaction(Transaction {
+                destination,
+                ..transaction(r#type, client_id, transaction_id, amount)
+            });
+        }
+        record_accounts(&mut registry.lock().unwrap(), &bank);
+
+        let output = registry.lock().unwrap().render();
+        // The destination of the transfer has an account, the customer of the reserved ID does not.
+        assert!(output.contains("tx_processor_accounts 3\n"));
+        assert!(output.contains("tx_processor_locked_accounts 0\n"));
+        assert!(output.contains("tx_processor_held_funds{currency=\"USD\"} 10\n"));
+        assert!(output.contains("tx_processor_transactions_total{type=\"deposit\",outcome=\"applied\"} 2\n"));
+        assert!(
+            output.contains("tx_processor_transactions_total{type=\"withdrawal\",outcome=\"rejected\"} 1\n")
+        );
+        assert!(output.contains("tx_processor_rejections_total{reason=\"insufficient_funds\"} 1\n"));
+        assert!(output.contains("tx_processor_rejections_total{reason=\"reserved_transaction\"} 1\n"));
+        assert!(output.contains("tx_processor_transaction_duration_seconds_count 6\n"));
+    }
+
+    #[test]
+    pub fn test_serve() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let registry = Arc::new(Mutex::new(Registry::new()));
+        registry.lock().unwrap().set("up", "Up.", &[], 1.0);
+        let served_registry = registry.clone();
+        thread::spawn(move || serve(listener, served_registry));
+
+        // A client that does not send its request does not block the others.
+        let _idle = TcpStream::connect(address).unwrap();
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("# HELP up Up.\n# TYPE up gauge\nup 1\n"));
+    }
+}
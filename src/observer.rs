@@ -0,0 +1,204 @@
+use rust_decimal::prelude::*;
+use serde::Serialize;
+use std::cell::RefCell;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::rc::Rc;
+
+use crate::ledger::LedgerEntry;
+use crate::transaction::TransactionType;
+
+/// Something that happened in the bank. Rows are numbered from 1, in the
+/// order in which the transactions were received by the bank.
+#[derive(Serialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// A row of the input could not be read as a transaction, and was skipped.
+    Malformed {
+        line: Option<u64>,
+        error: String,
+    },
+    /// A transaction is about to be processed. The line is the line of the
+    /// file holding the transaction, if it was read from a file, and the
+    /// destination is the account credited by a transfer. Synthetic
+    /// transactions, like interest credits, are posted by the bank: they
+    /// have the row of the last transaction received, and no line.
+    Received {
+        row: u64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        line: Option<u64>,
+        client_id: u16,
+        transaction_id: u32,
+        r#type: TransactionType,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        destination: Option<u16>,
+        #[serde(skip_serializing_if = "std::ops::Not::not")]
+        synthetic: bool,
+    },
+    /// A transaction was applied, with the movements of funds that it caused,
+    /// including the credit of the destination account of a transfer.
+    Applied {
+        row: u64,
+        client_id: u16,
+        transaction_id: u32,
+        r#type: TransactionType,
+        entries: Vec<LedgerEntry>,
+    },
+    /// A transaction was rejected. The reason is the code of the error.
+    Rejected {
+        row: u64,
+        client_id: u16,
+        transaction_id: u32,
+        r#type: TransactionType,
+        reason: String,
+        message: String,
+    },
+    DisputeOpened {
+        client_id: u16,
+        transaction_id: u32,
+        currency: String,
+        amount: Decimal,
+    },
+    DisputeResolved {
+        client_id: u16,
+        transaction_id: u32,
+        currency: String,
+        amount: Decimal,
+    },
+    ChargedBack {
+        client_id: u16,
+        transaction_id: u32,
+        currency: String,
+        amount: Decimal,
+    },
+    AccountLocked {
+        client_id: u16,
+        transaction_id: u32,
+    },
+    AccountFlagged {
+        client_id: u16,
+        flag: String,
+    },
+    /// The funds of an authorization were released because it expired.
+    AuthorizationExpired {
+        client_id: u16,
+        transaction_id: u32,
+        currency: String,
+        amount: Decimal,
+    },
+}
+
+/// Receives every event of the bank, in order.
+pub trait Observer {
+    fn notify(&mut self, event: &Event);
+}
+
+/// Writes every event as a line of JSON. The writer is flushed after every
+/// event, so that the file is complete even if the process exits abruptly.
+pub struct JsonlSink<W: Write> {
+    writer: W,
+}
+impl<W: Write> JsonlSink<W> {
+    pub fn new(writer: W) -> JsonlSink<W> {
+        JsonlSink { writer }
+    }
+}
+impl JsonlSink<BufWriter<File>> {
+    pub fn create(events_file_path: &str) -> io::Result<JsonlSink<BufWriter<File>>> {
+        Ok(JsonlSink::new(BufWriter::new(File::create(events_file_path)?)))
+    }
+}
+impl<W: Write> Observer for JsonlSink<W> {
+    fn notify(&mut self, event: &Event) {
+        let line = serde_json::to_string(event).expect("Could not serialize event.");
+        if let Err(err) = writeln!(self.writer, "{}", line).and_then(|_| self.writer.flush()) {
+            eprintln!("Could not write event: {}", err);
+        }
+    }
+}
+
+/// Collects the events in memory. The sink can be cloned before being
+/// added to the bank, so that the clone can read the events.
+#[derive(Clone)]
+#[derive(Default)]
+pub struct MemorySink {
+    events: Rc<RefCell<Vec<Event>>>,
+}
+impl MemorySink {
+    pub fn new() -> MemorySink {
+        MemorySink::default()
+    }
+
+    pub fn get_events(&self) -> Vec<Event> {
+        self.events.borrow().clone()
+    }
+}
+impl Observer for MemorySink {
+    fn notify(&mut self, event: &Event) {
+        self.events.borrow_mut().push(event.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_jsonl_sink() {
+        let mut sink = JsonlSink::new(vec![]);
+        sink.notify(&Event::Received {
+            row: 1,
+            line: None,
+            client_id: 2,
+            transaction_id: 3,
+            r#type: TransactionType::Deposit,
+            destination: None,
+            synthetic: false,
+        });
+        sink.notify(&Event::DisputeOpened {
+            client_id: 2,
+            transaction_id: 3,
+            currency: "USD".to_string(),
+            amount: Decimal::from_str("1.5").unwrap(),
+        });
+        assert_eq!(
+            String::from_utf8(sink.writer).unwrap(),
+            concat!(
+                r#"{"event":"received","row":1,"client_id":2,"transaction_id":3,"type":"deposit"}"#,
+                "\n",
+                r#"{"event":"dispute_opened","client_id":2,"transaction_id":3,"currency":"USD","amount":"1.5"}"#,
+                "\n"
+            )
+        );
+    }
+
+    #[test]
+    pub fn test_jsonl_sink_flush() {
+        let path = std::env::temp_dir().join(format!("tx-processor-events-{}.jsonl", std::process::id()));
+        let mut sink = JsonlSink::create(path.to_str().unwrap()).unwrap();
+        sink.notify(&Event::Received {
+            row: 1,
+            line: None,
+            client_id: 2,
+            transaction_id: 3,
+            r#type: TransactionType::Deposit,
+            destination: None,
+            synthetic: false,
+        });
+
+        // The event is in the file while the sink is still alive.
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            content,
+            concat!(
+                r#"{"event":"received","row":1,"client_id":2,"transaction_id":3,"type":"deposit"}"#,
+                "\n"
+            )
+        );
+    }
+}
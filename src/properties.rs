@@ -0,0 +1,286 @@
+// Property-based tests of the accounts: random sequences of transactions are
+// applied both to the engine and to a simple reference model, and their states
+// are compared after every transaction. A failing sequence can be replayed from
+// the seed given in the assertion message.
+
+use rust_decimal::prelude::*;
+use std::collections::HashMap;
+
+use crate::account::Account;
+use crate::bank::Bank;
+use crate::random::Random;
+use crate::transaction::{Transaction, TransactionType};
+
+// The number of random sequences checked by every property.
+const SEQUENCES: u64 = 200;
+
+// The number of transactions of every sequence.
+const LENGTH: usize = 100;
+
+// The transaction types of the sequences, picked according to weights.
+const TYPES: [TransactionType; 6] = [
+    TransactionType::Deposit,
+    TransactionType::Withdrawal,
+    TransactionType::Transfer,
+    TransactionType::Dispute,
+    TransactionType::Resolve,
+    TransactionType::Chargeback,
+];
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Default)]
+struct ModelTransaction {
+    amount: Decimal,
+
+    disputable: bool,
+
+    held: Decimal,
+
+    reversed: Decimal,
+}
+
+// The reference model of an account, in the default currency, without fees,
+// limits or authorizations.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Default)]
+struct ModelAccount {
+    available: Decimal,
+
+    held: Decimal,
+
+    locked: bool,
+
+    transactions: HashMap<u32, ModelTransaction>,
+}
+impl ModelAccount {
+    fn apply(&mut self, tx: &Transaction) -> Result<(), ()> {
+        if self.locked {
+            return Err(());
+        }
+        match tx.get_type() {
+            TransactionType::Deposit | TransactionType::Withdrawal | TransactionType::Transfer => {
+                if self.transactions.contains_key(&tx.transaction_id) {
+                    return Err(());
+                }
+                let amount = tx.amount.ok_or(())?;
+                if *tx.get_type() == TransactionType::Deposit {
+                    self.available += amount;
+                } else if amount > self.available {
+                    return Err(());
+                } else {
+                    self.available -= amount;
+                }
+                let transaction = ModelTransaction {
+                    amount,
+                    // Transfers cannot be disputed with the default configuration.
+                    disputable: *tx.get_type() != TransactionType::Transfer,
+                    ..ModelTransaction::default()
+                };
+                self.transactions.insert(tx.transaction_id, transaction);
+            }
+            TransactionType::Dispute => {
+                let transaction = self.transactions.get_mut(&tx.transaction_id).ok_or(())?;
+                let remaining = transaction.amount - transaction.held - transaction.reversed;
+                if !transaction.disputable || remaining.is_zero() {
+                    return Err(());
+                }
+                let amount = get_partial_amount(tx, remaining)?;
+                if amount > self.available {
+                    return Err(());
+                }
+                transaction.held += amount;
+                self.available -= amount;
+                self.held += amount;
+            }
+            TransactionType::Resolve | TransactionType::Chargeback => {
+                let transaction = self.transactions.get_mut(&tx.transaction_id).ok_or(())?;
+                if transaction.held.is_zero() {
+                    return Err(());
+                }
+                let amount = get_partial_amount(tx, transaction.held)?;
+                transaction.held -= amount;
+                self.held -= amount;
+                if *tx.get_type() == TransactionType::Resolve {
+                    self.available += amount;
+                } else {
+                    transaction.reversed += amount;
+                    self.locked = true;
+                }
+            }
+            _ => return Err(()),
+        }
+        Ok(())
+    }
+}
+
+fn get_partial_amount(tx: &Transaction, maximum: Decimal) -> Result<Decimal, ()> {
+    match tx.amount {
+        None => Ok(maximum),
+        Some(amount) if amount <= Decimal::ZERO || amount > maximum => Err(()),
+        Some(amount) => Ok(amount),
+    }
+}
+
+// The reference model of a bank, indexed by customer ID.
+#[derive(Debug)]
+#[derive(Default)]
+struct ModelBank {
+    accounts: HashMap<u16, ModelAccount>,
+}
+impl ModelBank {
+    // The account of the customer is created even if the transaction is
+    // rejected, and the account of the destination of a transfer only if
+    // the transfer is applied.
+    fn apply(&mut self, tx: &Transaction) -> Result<(), ()> {
+        self.accounts.entry(tx.client_id).or_default();
+        if *tx.get_type() != TransactionType::Transfer {
+            return self.accounts.get_mut(&tx.client_id).unwrap().apply(tx);
+        }
+        let destination = tx.destination.ok_or(())?;
+        if destination == tx.client_id
+            || self
+                .accounts
+                .get(&destination)
+                .is_some_and(|account| account.locked)
+        {
+            return Err(());
+        }
+        self.accounts.get_mut(&tx.client_id).unwrap().apply(tx)?;
+        self.accounts.entry(destination).or_default().available += tx.amount.unwrap();
+        Ok(())
+    }
+}
+
+fn random_transaction(random: &mut Random, clients: u16, transaction_ids: u32, weights: &[u32]) -> Transaction {
+    let r#type = TYPES[random.pick_weighted(weights)].clone();
+    let amount = match r#type {
+        TransactionType::Deposit | TransactionType::Withdrawal | TransactionType::Transfer => {
+            Some(random.decimal(Decimal::new(1, 2), Decimal::from(100), 2))
+        }
+        // Disputes, resolves and chargebacks are sometimes partial.
+        _ if random.percent(25.0) => Some(random.decimal(Decimal::new(1, 2), Decimal::from(50), 2)),
+        _ => None,
+    };
+    let destination = match r#type {
+        TransactionType::Transfer => Some(random.below(clients as u64) as u16 + 1),
+        _ => None,
+    };
+    Transaction {
+        client_id: random.below(clients as u64) as u16 + 1,
+        transaction_id: random.below(transaction_ids as u64) as u32 + 1,
+        r#type,
+        amount,
+        currency: None,
+        destination,
+        timestamp: None,
+    }
+}
+
+// Checks the invariants of an account, and compares it with its model.
+fn check_account(account: &Account, model: &ModelAccount, context: &str) {
+    let balance = account.get_balance("USD");
+    assert_eq!(balance.available, model.available, "{}", context);
+    assert_eq!(balance.held, model.held, "{}", context);
+    assert_eq!(account.is_locked(), model.locked, "{}", context);
+
+    let row = &account.get_rows()[0];
+    assert_eq!(row.total, row.available + row.held, "{}", context);
+    let disputed: Decimal = account
+        .get_open_disputes()
+        .iter()
+        .map(|dispute| dispute.held)
+        .sum();
+    assert_eq!(balance.held, disputed, "{}", context);
+    assert!(account.audit().is_empty(), "{}", context);
+}
+
+#[test]
+pub fn test_account_properties() {
+    for seed in 0..SEQUENCES {
+        let mut random = Random::new(seed);
+        let mut account = Account::new(1);
+        let mut model = ModelAccount::default();
+        for step in 0..LENGTH {
+            let tx = random_transaction(&mut random, 1, 30, &[30, 20, 0, 20, 10, 3]);
+            let context = format!("seed {}, step {}: {:?}", seed, step, tx);
+            let (before, was_locked) = (account.get_balance("USD"), account.is_locked());
+
+            let result = account.process_transaction(tx.clone());
+            assert_eq!(result.is_ok(), model.apply(&tx).is_ok(), "{}", context);
+            check_account(&account, &model, &context);
+            // Rejected transactions, and every transaction of a locked account,
+            // leave the balances unchanged.
+            if result.is_err() || was_locked {
+                assert_eq!(account.get_balance("USD"), before, "{}", context);
+            }
+        }
+    }
+}
+
+#[test]
+pub fn test_duplicate_transactions() {
+    for seed in 0..SEQUENCES {
+        let mut random = Random::new(seed);
+        let mut account = Account::new(1);
+        let mut processed = vec![];
+        for _ in 0..LENGTH {
+            let tx = random_transaction(&mut random, 1, 1000, &[3, 2, 0, 0, 0, 0]);
+            if account.process_transaction(tx.clone()).is_ok() {
+                processed.push(tx);
+            }
+        }
+
+        // Replaying the deposits and withdrawals, even with other amounts, changes nothing.
+        let before = account.get_balance("USD");
+        for tx in processed {
+            let replayed = Transaction {
+                amount: Some(random.decimal(Decimal::new(1, 2), Decimal::from(100), 2)),
+                ..tx.clone()
+            };
+            for tx in [tx, replayed] {
+                let context = format!("seed {}: {:?}", seed, tx);
+                assert!(account.process_transaction(tx).is_err(), "{}", context);
+                assert_eq!(account.get_balance("USD"), before, "{}", context);
+            }
+        }
+    }
+}
+
+#[test]
+pub fn test_bank_properties() {
+    let clients = 5;
+    for seed in 0..SEQUENCES {
+        let mut random = Random::new(seed);
+        let mut bank = Bank::new();
+        let mut model = ModelBank::default();
+        // The funds deposited minus the funds withdrawn and charged back.
+        let mut funds = Decimal::ZERO;
+        for step in 0..LENGTH {
+            let tx = random_transaction(&mut random, clients, 60, &[30, 15, 15, 20, 10, 3]);
+            let context = format!("seed {}, step {}: {:?}", seed, step, tx);
+            let held = model.accounts.get(&tx.client_id).map(|account| account.held);
+
+            bank.process_transaction(tx.clone());
+            if model.apply(&tx).is_ok() {
+                match tx.get_type() {
+                    TransactionType::Deposit => funds += tx.amount.unwrap(),
+                    TransactionType::Withdrawal => funds -= tx.amount.unwrap(),
+                    TransactionType::Chargeback => funds -= held.unwrap() - model.accounts[&tx.client_id].held,
+                    _ => (),
+                }
+            }
+
+            for client_id in 1..=clients {
+                match (bank.accounts.get(&client_id), model.accounts.get(&client_id)) {
+                    (Some(account), Some(model)) => check_account(account, model, &context),
+                    (account, model) => assert!(account.is_none() && model.is_none(), "{}", context),
+                }
+            }
+            let total: Decimal = bank.get_rows().iter().map(|row| row.total).sum();
+            assert_eq!(total, funds, "{}", context);
+        }
+        assert!(bank.audit().is_empty(), "seed {}", seed);
+    }
+}
@@ -0,0 +1,85 @@
+use rust_decimal::prelude::*;
+
+/// A small pseudo-random number generator (SplitMix64), so that generated
+/// data can be reproduced from a seed. It is not suitable for cryptography.
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Random {
+    state: u64,
+}
+impl Random {
+    pub fn new(seed: u64) -> Random {
+        Random { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Gets a number in `[0, bound)`. The bound must not be zero.
+    pub fn below(&mut self, bound: u64) -> u64 {
+        ((self.next_u64() as u128 * bound as u128) >> 64) as u64
+    }
+
+    /// Returns true with the given probability, in percent.
+    pub fn percent(&mut self, percent: f64) -> bool {
+        let value = (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+        value * 100.0 < percent
+    }
+
+    /// Picks an item of a slice, which must not be empty.
+    pub fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len() as u64) as usize]
+    }
+
+    /// Picks the index of an item according to its weight. The weights must
+    /// not all be zero.
+    pub fn pick_weighted(&mut self, weights: &[u32]) -> usize {
+        let total: u64 = weights.iter().map(|weight| *weight as u64).sum();
+        let mut value = self.below(total);
+        for (index, weight) in weights.iter().enumerate() {
+            if value < *weight as u64 {
+                return index;
+            }
+            value -= *weight as u64;
+        }
+        unreachable!()
+    }
+
+    /// Gets a decimal in `[min, max]` with the given number of decimals.
+    /// `max` scaled by the decimals must fit in an i64.
+    pub fn decimal(&mut self, min: Decimal, max: Decimal, decimals: u32) -> Decimal {
+        let scale = Decimal::from(10u64.pow(decimals));
+        let low = (min * scale).ceil().to_i64().unwrap_or(0);
+        let high = (max * scale).floor().to_i64().unwrap_or(0).max(low);
+        Decimal::new(low + self.below((high - low) as u64 + 1) as i64, decimals)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_random() {
+        let mut random = Random::new(42);
+        let values: Vec<u64> = (0..5).map(|_| random.next_u64()).collect();
+        let mut other = Random::new(42);
+        assert_eq!(values, (0..5).map(|_| other.next_u64()).collect::<Vec<u64>>());
+        assert_ne!(Random::new(43).next_u64(), values[0]);
+
+        let (min, max) = (Decimal::from_str("0.5").unwrap(), Decimal::from_str("2").unwrap());
+        for _ in 0..1000 {
+            assert!(random.below(3) < 3);
+            let amount = random.decimal(min, max, 2);
+            assert!(amount >= min && amount <= max && amount.scale() == 2);
+            assert_ne!(random.pick_weighted(&[1, 0, 3]), 1);
+        }
+        assert!(!random.percent(0.0));
+        assert!(random.percent(100.0));
+    }
+}
@@ -0,0 +1,247 @@
+use rust_decimal::prelude::*;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+
+use crate::config::Config;
+use crate::snapshot::AccountRow;
+use crate::trail::{TrailCollector, TrailEntry};
+
+/// The balance of a customer expected by another system. Only the
+/// columns present in the file are compared.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+#[derive(Deserialize)]
+pub struct ExpectedBalance {
+    pub client: u16,
+
+    /// The currency of the funds, the default currency when empty.
+    #[serde(default)]
+    pub currency: String,
+
+    pub available: Option<Decimal>,
+
+    pub held: Option<Decimal>,
+
+    pub total: Option<Decimal>,
+
+    pub locked: Option<bool>,
+}
+
+/// Reads the expected balances from a CSV file.
+pub fn read_expected_balances(path: &str) -> Result<Vec<ExpectedBalance>, Box<dyn Error>> {
+    let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_path(path)?;
+    let mut balances = vec![];
+    for balance in reader.deserialize() {
+        balances.push(balance?);
+    }
+    Ok(balances)
+}
+
+/// A difference between the expected and the actual state of an account.
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum Mismatch {
+    Balance {
+        currency: String,
+        field: &'static str,
+        expected: String,
+        actual: String,
+    },
+    /// An expected balance with no matching account.
+    MissingAccount { currency: String },
+    /// An account holding funds or locked, with no expected balance.
+    UnexpectedAccount { currency: String },
+}
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mismatch::Balance {
+                currency,
+                field,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{} {}: expected {}, actual {}",
+                currency, field, expected, actual
+            ),
+            Mismatch::MissingAccount { currency } => write!(f, "{}: no account", currency),
+            Mismatch::UnexpectedAccount { currency } => write!(f, "{}: no expected balance", currency),
+        }
+    }
+}
+
+/// A customer whose accounts do not match the expected balances, with
+/// the transactions that touched the customer.
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct Break {
+    pub client_id: u16,
+
+    pub mismatches: Vec<Mismatch>,
+
+    pub transactions: Vec<TrailEntry>,
+}
+impl fmt::Display for Break {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Client {}:", self.client_id)?;
+        for mismatch in &self.mismatches {
+            writeln!(f, "  {}", mismatch)?;
+        }
+        if self.transactions.is_empty() {
+            writeln!(f, "  No transactions.")?;
+        }
+        for transaction in &self.transactions {
+            writeln!(f, "  {}", transaction)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compares the accounts of the bank with the expected balances, by customer
+/// and currency, and returns the breaks ordered by customer ID. The expected
+/// amounts are rounded to the precision of their currency before they are compared.
+pub fn reconcile(
+    rows: &[AccountRow],
+    expected: &[ExpectedBalance],
+    trail: &TrailCollector,
+    config: &Config,
+) -> Vec<Break> {
+    let get_currency = |currency: &str| match currency.is_empty() {
+        true => config.default_currency.to_uppercase(),
+        false => currency.to_uppercase(),
+    };
+    let actual: BTreeMap<(u16, String), &AccountRow> = rows
+        .iter()
+        .map(|row| ((row.client, get_currency(&row.currency)), row))
+        .collect();
+    let expected: BTreeMap<(u16, String), &ExpectedBalance> = expected
+        .iter()
+        .map(|balance| ((balance.client, get_currency(&balance.currency)), balance))
+        .collect();
+
+    let mut mismatches: BTreeMap<u16, Vec<Mismatch>> = BTreeMap::new();
+    for ((client_id, currency), e) in &expected {
+        let a = match actual.get(&(*client_id, currency.clone())) {
+            Some(a) => a,
+            None => {
+                let currency = currency.clone();
+                mismatches
+                    .entry(*client_id)
+                    .or_default()
+                    .push(Mismatch::MissingAccount { currency });
+                continue;
+            }
+        };
+        let precision = config.get_precision(currency);
+        for (field, e, a) in [
+            ("available", e.available, a.available),
+            ("held", e.held, a.held),
+            ("total", e.total, a.total),
+        ] {
+            let e = match e {
+                Some(e) => precision.round(e).normalize(),
+                None => continue,
+            };
+            if e != a.normalize() {
+                mismatches.entry(*client_id).or_default().push(Mismatch::Balance {
+                    currency: currency.clone(),
+                    field,
+                    expected: e.to_string(),
+                    actual: a.normalize().to_string(),
+                });
+            }
+        }
+        if let Some(locked) = e.locked.filter(|locked| *locked != a.locked) {
+            mismatches.entry(*client_id).or_default().push(Mismatch::Balance {
+                currency: currency.clone(),
+                field: "locked",
+                expected: locked.to_string(),
+                actual: a.locked.to_string(),
+            });
+        }
+    }
+    for ((client_id, currency), a) in &actual {
+        if !expected.contains_key(&(*client_id, currency.clone())) && (!a.total.is_zero() || a.locked) {
+            let currency = currency.clone();
+            mismatches
+                .entry(*client_id)
+                .or_default()
+                .push(Mismatch::UnexpectedAccount { currency });
+        }
+    }
+
+    mismatches
+        .into_iter()
+        .map(|(client_id, mismatches)| Break {
+            client_id,
+            mismatches,
+            transactions: trail.get_trail(client_id),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank::Bank;
+    use crate::test_support::transaction;
+    use crate::transaction::TransactionType;
+
+    fn expected(client: u16, available: Option<&str>, total: Option<&str>) -> ExpectedBalance {
+        ExpectedBalance {
+            client,
+            currency: "".to_string(),
+            available: available.map(|a| Decimal::from_str(a).unwrap()),
+            held: None,
+            total: total.map(|t| Decimal::from_str(t).unwrap()),
+            locked: None,
+        }
+    }
+
+    #[test]
+    pub fn test_reconcile() {
+        let mut bank = Bank::new();
+        let trail = TrailCollector::new();
+        bank.add_observer(trail.clone());
+        bank.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some("10.0")));
+        bank.process_transaction(transaction(TransactionType::Deposit, 2, 2, Some("5.0")));
+        bank.process_transaction(transaction(TransactionType::Withdrawal, 2, 3, Some("2.0")));
+        bank.process_transaction(transaction(TransactionType::Deposit, 3, 4, Some("1.0")));
+
+        let breaks = reconcile(
+            &bank.get_rows(),
+            &[
+                expected(1, Some("10.00001"), None),
+                expected(2, Some("5.0"), Some("3.0")),
+                expected(4, None, Some("0")),
+            ],
+            &trail,
+            &Config::default(),
+        );
+        assert_eq!(breaks.len(), 3);
+        assert_eq!(breaks[0].client_id, 2);
+        assert_eq!(
+            breaks[0].to_string(),
+            "Client 2:\n  USD available: expected 5, actual 3\n  \
+             row 2: deposit 2 of client 2: applied (USD available +5)\n  \
+             row 3: withdrawal 3 of client 2: applied (USD available -2)\n"
+        );
+        assert_eq!(
+            breaks[1].mismatches,
+            vec![Mismatch::UnexpectedAccount {
+                currency: "USD".to_string()
+            }]
+        );
+        assert_eq!(breaks[2].client_id, 4);
+        assert_eq!(
+            breaks[2].mismatches,
+            vec![Mismatch::MissingAccount {
+                currency: "USD".to_string()
+            }]
+        );
+    }
+}
@@ -0,0 +1,251 @@
+use rust_decimal::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+
+use crate::config::Config;
+
+/// The state of an account in a single currency, as printed by the bank.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+#[derive(Serialize)]
+#[derive(Deserialize)]
+pub struct AccountRow {
+    pub client: u16,
+
+    /// The currency of the funds. Outputs without currencies hold
+    /// funds in the default currency.
+    #[serde(default)]
+    pub currency: String,
+
+    pub available: Decimal,
+
+    pub held: Decimal,
+
+    #[serde(default)]
+    pub reserved: Decimal,
+
+    pub total: Decimal,
+
+    pub locked: bool,
+
+    /// The flags raised on the account, separated by `|`.
+    #[serde(default)]
+    pub flags: String,
+}
+
+/// The format of the accounts printed by the bank.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+pub enum OutputFormat {
+    Csv,
+    Json,
+}
+impl OutputFormat {
+    pub fn from_string(format: &str) -> Option<OutputFormat> {
+        match format.to_lowercase().as_str() {
+            "csv" => Some(OutputFormat::Csv),
+            "json" => Some(OutputFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Reads the accounts printed by the bank, either as CSV or as JSON.
+pub fn read_snapshot(path: &str) -> Result<Vec<AccountRow>, Box<dyn Error>> {
+    parse_snapshot(&fs::read_to_string(path)?)
+}
+
+/// Parses the accounts printed by the bank. The content is read as JSON
+/// when it starts with `[`, and as CSV otherwise.
+pub fn parse_snapshot(content: &str) -> Result<Vec<AccountRow>, Box<dyn Error>> {
+    if content.trim_start().starts_with('[') {
+        return Ok(serde_json::from_str(content)?);
+    }
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(content.as_bytes());
+    let mut rows = vec![];
+    for row in reader.deserialize() {
+        rows.push(row?);
+    }
+    Ok(rows)
+}
+
+/// A difference between two snapshots of the accounts.
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum Difference {
+    /// A field of an account differs between the two snapshots.
+    Changed {
+        client_id: u16,
+        currency: String,
+        field: &'static str,
+        left: String,
+        right: String,
+    },
+    /// An account only appears in the left snapshot.
+    OnlyLeft { client_id: u16, currency: String },
+    /// An account only appears in the right snapshot.
+    OnlyRight { client_id: u16, currency: String },
+}
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Difference::Changed {
+                client_id,
+                currency,
+                field,
+                left,
+                right,
+            } => write!(
+                f,
+                "client {} {}: {} {} -> {}",
+                client_id, currency, field, left, right
+            ),
+            Difference::OnlyLeft { client_id, currency } => {
+                write!(f, "client {} {}: only in the left snapshot", client_id, currency)
+            }
+            Difference::OnlyRight { client_id, currency } => {
+                write!(f, "client {} {}: only in the right snapshot", client_id, currency)
+            }
+        }
+    }
+}
+
+/// Compares two snapshots of the accounts, by customer and currency.
+/// The amounts are rounded to the precision of their currency before
+/// they are compared, so that `1.00001` and `1` match with 4 decimals.
+pub fn diff(left: &[AccountRow], right: &[AccountRow], config: &Config) -> Vec<Difference> {
+    let left = index(left, config);
+    let right = index(right, config);
+
+    let mut differences = vec![];
+    for ((client_id, currency), l) in &left {
+        let r = match right.get(&(*client_id, currency.clone())) {
+            Some(r) => r,
+            None => {
+                differences.push(Difference::OnlyLeft {
+                    client_id: *client_id,
+                    currency: currency.clone(),
+                });
+                continue;
+            }
+        };
+        let precision = config.get_precision(currency);
+        for (field, l, r) in [
+            ("available", l.available, r.available),
+            ("held", l.held, r.held),
+            ("total", l.total, r.total),
+        ] {
+            let (l, r) = (precision.round(l).normalize(), precision.round(r).normalize());
+            if l != r {
+                differences.push(Difference::Changed {
+                    client_id: *client_id,
+                    currency: currency.clone(),
+                    field,
+                    left: l.to_string(),
+                    right: r.to_string(),
+                });
+            }
+        }
+        if l.locked != r.locked {
+            differences.push(Difference::Changed {
+                client_id: *client_id,
+                currency: currency.clone(),
+                field: "locked",
+                left: l.locked.to_string(),
+                right: r.locked.to_string(),
+            });
+        }
+    }
+    for (client_id, currency) in right.keys().filter(|key| !left.contains_key(key)) {
+        differences.push(Difference::OnlyRight {
+            client_id: *client_id,
+            currency: currency.clone(),
+        });
+    }
+    differences.sort_by_key(|difference| match difference {
+        Difference::Changed {
+            client_id, currency, ..
+        }
+        | Difference::OnlyLeft { client_id, currency }
+        | Difference::OnlyRight { client_id, currency } => (*client_id, currency.clone()),
+    });
+    differences
+}
+
+// Indexes the rows of a snapshot by customer and currency.
+fn index<'a>(rows: &'a [AccountRow], config: &Config) -> BTreeMap<(u16, String), &'a AccountRow> {
+    rows.iter()
+        .map(|row| {
+            let currency = match row.currency.is_empty() {
+                true => config.default_currency.to_uppercase(),
+                false => row.currency.to_uppercase(),
+            };
+            ((row.client, currency), row)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_parse_snapshot() {
+        let csv = "client, currency, available, held, reserved, total, locked, flags\n\
+                   1, USD, 1.5, 0, 0, 1.5, false, \n\
+                   2, EUR, 3, 1, 0, 4, true, hold|overdrawn\n";
+        let rows = parse_snapshot(csv).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1].currency, "EUR");
+        assert_eq!(rows[1].flags, "hold|overdrawn");
+        assert!(rows[1].locked);
+
+        let json = serde_json::to_string(&rows).unwrap();
+        assert_eq!(parse_snapshot(&json).unwrap(), rows);
+
+        // Outputs from before currencies were supported.
+        let rows = parse_snapshot("client, available, held, total, locked\n1, 1.5, 0, 1.5, false\n").unwrap();
+        assert_eq!(rows[0].currency, "");
+        assert_eq!(rows[0].reserved, Decimal::ZERO);
+    }
+
+    #[test]
+    pub fn test_diff() {
+        let config = Config::default();
+        let left = parse_snapshot(
+            "client, available, held, total, locked\n\
+             1, 1.5, 0, 1.5, false\n\
+             2, 3, 1, 4, false\n\
+             3, 1, 0, 1, false\n",
+        )
+        .unwrap();
+        let right = parse_snapshot(
+            "client, currency, available, held, reserved, total, locked, flags\n\
+             1, usd, 1.50001, 0, 0, 1.50001, false,\n\
+             2, USD, 4, 0, 0, 4, true,\n\
+             4, USD, 1, 0, 0, 1, false,\n",
+        )
+        .unwrap();
+
+        assert!(diff(&left, &left, &config).is_empty());
+        let differences = diff(&left, &right, &config);
+        assert_eq!(
+            differences.iter().map(|d| d.to_string()).collect::<Vec<String>>(),
+            vec![
+                "client 2 USD: available 3 -> 4",
+                "client 2 USD: held 1 -> 0",
+                "client 2 USD: locked false -> true",
+                "client 3 USD: only in the left snapshot",
+                "client 4 USD: only in the right snapshot",
+            ]
+        );
+    }
+}
@@ -0,0 +1,245 @@
+use rust_decimal::prelude::*;
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::rc::Rc;
+use std::time::Instant;
+
+use crate::ledger::EntryKind;
+use crate::observer::{Event, Observer};
+use crate::transaction::TransactionType;
+
+/// The totals of a run of the bank.
+#[derive(Serialize)]
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Default)]
+#[derive(PartialEq)]
+pub struct Summary {
+    /// The rows of the input, malformed rows included.
+    pub rows_read: u64,
+
+    pub rows_malformed: u64,
+
+    pub rows_applied: u64,
+
+    pub rows_rejected: u64,
+
+    /// The rows applied, indexed by transaction type.
+    pub applied_by_type: BTreeMap<String, u64>,
+
+    /// The rows rejected, indexed by transaction type.
+    pub rejected_by_type: BTreeMap<String, u64>,
+
+    /// The rows rejected, indexed by the code of the reason.
+    pub rejected_by_reason: BTreeMap<String, u64>,
+
+    /// The funds deposited, indexed by currency.
+    pub deposited: BTreeMap<String, Decimal>,
+
+    /// The funds withdrawn or captured, indexed by currency.
+    pub withdrawn: BTreeMap<String, Decimal>,
+
+    pub disputes_opened: u64,
+
+    pub disputes_resolved: u64,
+
+    pub chargebacks: u64,
+
+    /// The accounts locked during the run.
+    pub locked_accounts: Vec<u16>,
+
+    /// The fees credited to the bank, indexed by currency.
+    pub revenue: BTreeMap<String, Decimal>,
+
+    pub elapsed_seconds: f64,
+
+    /// The number of rows read per second.
+    pub rows_per_second: f64,
+}
+impl Summary {
+    /// Prints the summary to `stderr`.
+    pub fn print(&self) {
+        eprintln!("Rows read: {}", self.rows_read);
+        eprintln!("Rows malformed: {}", self.rows_malformed);
+        eprintln!("Rows applied: {}", self.rows_applied);
+        for (r#type, count) in &self.applied_by_type {
+            eprintln!("  {}: {}", r#type, count);
+        }
+        eprintln!("Rows rejected: {}", self.rows_rejected);
+        for (r#type, count) in &self.rejected_by_type {
+            eprintln!("  {}: {}", r#type, count);
+        }
+        eprintln!("Rejection reasons:");
+        for (reason, count) in &self.rejected_by_reason {
+            eprintln!("  {}: {}", reason, count);
+        }
+        for (currency, amount) in &self.deposited {
+            eprintln!("Deposited: {} {}", amount.normalize(), currency);
+        }
+        for (currency, amount) in &self.withdrawn {
+            eprintln!("Withdrawn: {} {}", amount.normalize(), currency);
+        }
+        for (currency, amount) in &self.revenue {
+            eprintln!("Revenue: {} {}", amount.normalize(), currency);
+        }
+        eprintln!("Disputes opened: {}", self.disputes_opened);
+        eprintln!("Disputes resolved: {}", self.disputes_resolved);
+        eprintln!("Chargebacks: {}", self.chargebacks);
+        eprintln!("Accounts locked: {}", self.locked_accounts.len());
+        eprintln!(
+            "Elapsed: {:.3}s ({:.0} rows per second)",
+            self.elapsed_seconds, self.rows_per_second
+        );
+    }
+
+    /// Writes the summary to a file, as JSON.
+    pub fn write(&self, summary_file_path: &str) -> io::Result<()> {
+        fs::write(summary_file_path, serde_json::to_string_pretty(self)?)
+    }
+}
+
+/// Builds the summary of a run from the events of the bank. The
+/// collector can be cloned before being added to the bank, so that
+/// the clone can read the summary.
+#[derive(Clone)]
+pub struct SummaryCollector {
+    summary: Rc<RefCell<Summary>>,
+
+    started: Instant,
+
+    // Whether the transaction being processed was posted by the bank,
+    // in which case it is not a row of the input.
+    synthetic: bool,
+}
+impl Default for SummaryCollector {
+    fn default() -> SummaryCollector {
+        SummaryCollector::new()
+    }
+}
+impl SummaryCollector {
+    pub fn new() -> SummaryCollector {
+        SummaryCollector {
+            summary: Rc::new(RefCell::new(Summary::default())),
+            started: Instant::now(),
+            synthetic: false,
+        }
+    }
+
+    /// Gets the summary, timed since the creation of the collector.
+    pub fn get_summary(&self) -> Summary {
+        let mut summary = self.summary.borrow().clone();
+        summary.elapsed_seconds = self.started.elapsed().as_secs_f64();
+        if summary.elapsed_seconds > 0.0 {
+            summary.rows_per_second = summary.rows_read as f64 / summary.elapsed_seconds;
+        }
+        summary
+    }
+}
+impl Observer for SummaryCollector {
+    fn notify(&mut self, event: &Event) {
+        let mut summary = self.summary.borrow_mut();
+        match event {
+            Event::Malformed { .. } => {
+                summary.rows_read += 1;
+                summary.rows_malformed += 1;
+            }
+            Event::Received { synthetic, .. } => {
+                self.synthetic = *synthetic;
+                if !synthetic {
+                    summary.rows_read += 1;
+                }
+            }
+            Event::Applied { r#type, entries, .. } => {
+                if !self.synthetic {
+                    summary.rows_applied += 1;
+                    *summary
+                        .applied_by_type
+                        .entry(r#type.get_name().to_string())
+                        .or_default() += 1;
+                }
+                for entry in entries
+                    .iter()
+                    .filter(|entry| entry.kind == EntryKind::Transaction)
+                {
+                    match r#type {
+                        TransactionType::Deposit => {
+                            *summary.deposited.entry(entry.currency.clone()).or_default() += entry.available;
+                        }
+                        TransactionType::Withdrawal | TransactionType::Capture => {
+                            *summary.withdrawn.entry(entry.currency.clone()).or_default() -=
+                                entry.available + entry.reserved;
+                        }
+                        _ => {}
+                    };
+                }
+            }
+            Event::Rejected { r#type, reason, .. } => {
+                summary.rows_rejected += 1;
+                *summary
+                    .rejected_by_type
+                    .entry(r#type.get_name().to_string())
+                    .or_default() += 1;
+                *summary.rejected_by_reason.entry(reason.clone()).or_default() += 1;
+            }
+            Event::DisputeOpened { .. } => summary.disputes_opened += 1,
+            Event::DisputeResolved { .. } => summary.disputes_resolved += 1,
+            Event::ChargedBack { .. } => summary.chargebacks += 1,
+            Event::AccountLocked { client_id, .. } => summary.locked_accounts.push(*client_id),
+            _ => {}
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank::Bank;
+    use crate::test_support::transaction;
+
+    #[test]
+    pub fn test_summary() {
+        let mut bank = Bank::new();
+        let collector = SummaryCollector::new();
+        bank.add_observer(collector.clone());
+        bank.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some("10.0")));
+        bank.process_transaction(transaction(TransactionType::Deposit, 1, 2, Some("5.0")));
+        bank.process_transaction(transaction(TransactionType::Withdrawal, 1, 3, Some("3.0")));
+        bank.process_transaction(transaction(TransactionType::Withdrawal, 1, 4, Some("30.0")));
+        bank.process_transaction(transaction(TransactionType::Dispute, 1, 1, None));
+        bank.process_transaction(transaction(TransactionType::Chargeback, 1, 1, None));
+        bank.process_transaction(transaction(TransactionType::Deposit, 1, 5, Some("1.0")));
+
+        let summary = collector.get_summary();
+        assert_eq!(summary.rows_read, 7);
+        assert_eq!(summary.rows_applied, 5);
+        assert_eq!(summary.rows_rejected, 2);
+        assert_eq!(summary.applied_by_type["deposit"], 2);
+        assert_eq!(summary.rejected_by_type["withdrawal"], 1);
+        assert_eq!(summary.rejected_by_reason["insufficient_funds"], 1);
+        assert_eq!(summary.rejected_by_reason["account_locked"], 1);
+        assert_eq!(summary.deposited["USD"], Decimal::from_str("15.0").unwrap());
+        assert_eq!(summary.withdrawn["USD"], Decimal::from_str("3.0").unwrap());
+        assert_eq!(summary.disputes_opened, 1);
+        assert_eq!(summary.chargebacks, 1);
+        assert_eq!(summary.locked_accounts, vec![1]);
+    }
+
+    #[test]
+    pub fn test_summary_interest() {
+        let config = serde_json::from_str(r#"{"interest": {"tiers": [{"percent": "1"}]}}"#).unwrap();
+        let mut bank = Bank::with_config(config);
+        let collector = SummaryCollector::new();
+        bank.add_observer(collector.clone());
+        bank.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some("100.0")));
+        bank.accrue_interest().unwrap();
+
+        // The interest credits are not rows of the input.
+        let summary = collector.get_summary();
+        assert_eq!(summary.rows_read, 1);
+        assert_eq!(summary.rows_applied, 1);
+        assert!(!summary.applied_by_type.contains_key("interest"));
+    }
+}
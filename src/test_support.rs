@@ -0,0 +1,31 @@
+// Helpers shared by the tests of the modules.
+
+use rust_decimal::prelude::*;
+
+use crate::transaction::{Transaction, TransactionType};
+
+/// Creates a transaction in the default currency, without destination or timestamp.
+pub fn transaction(
+    r#type: TransactionType,
+    client_id: u16,
+    transaction_id: u32,
+    amount: Option<&str>,
+) -> Transaction {
+    Transaction {
+        client_id,
+        transaction_id,
+        r#type,
+        amount: amount.map(|a| Decimal::from_str(a).unwrap()),
+        currency: None,
+        destination: None,
+        timestamp: None,
+    }
+}
+
+/// Creates a transfer in the default currency.
+pub fn transfer(client_id: u16, transaction_id: u32, amount: &str, destination: u16) -> Transaction {
+    Transaction {
+        destination: Some(destination),
+        ..transaction(TransactionType::Transfer, client_id, transaction_id, Some(amount))
+    }
+}
@@ -0,0 +1,207 @@
+use serde::de::{self, Visitor};
+use serde::Deserializer;
+use std::convert::TryFrom;
+use std::fmt;
+
+/// Parses a timestamp, either as a number of seconds since the Unix epoch,
+/// or as an RFC 3339 date and time like `2022-03-14T15:09:26Z`. Fractions
+/// of seconds are ignored.
+pub fn parse_timestamp(timestamp: &str) -> Result<i64, String> {
+    if let Ok(seconds) = timestamp.parse::<i64>() {
+        return Ok(seconds);
+    }
+    parse_rfc3339(timestamp).ok_or(format!("Invalid timestamp {}.", timestamp))
+}
+
+/// Deserializes an optional timestamp, either from a number of seconds
+/// since the Unix epoch or from a string accepted by `parse_timestamp`.
+pub fn deserialize_timestamp<'de, D>(deserializer: D) -> Result<Option<i64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserializer.deserialize_option(TimestampVisitor)
+}
+
+struct TimestampVisitor;
+impl<'de> Visitor<'de> for TimestampVisitor {
+    type Value = Option<i64>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "a number of seconds since the Unix epoch or an RFC 3339 date and time"
+        )
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Option<i64>, E> {
+        Ok(None)
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Option<i64>, E> {
+        Ok(None)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Option<i64>, D::Error> {
+        deserializer.deserialize_any(TimestampVisitor)
+    }
+
+    fn visit_i64<E: de::Error>(self, seconds: i64) -> Result<Option<i64>, E> {
+        Ok(Some(seconds))
+    }
+
+    fn visit_u64<E: de::Error>(self, seconds: u64) -> Result<Option<i64>, E> {
+        match i64::try_from(seconds) {
+            Ok(seconds) => Ok(Some(seconds)),
+            Err(_) => Err(E::custom(format!("Invalid timestamp {}.", seconds))),
+        }
+    }
+
+    fn visit_str<E: de::Error>(self, timestamp: &str) -> Result<Option<i64>, E> {
+        parse_timestamp(timestamp).map(Some).map_err(E::custom)
+    }
+}
+
+fn parse_rfc3339(timestamp: &str) -> Option<i64> {
+    if !timestamp.is_ascii() || timestamp.len() < 20 {
+        return None;
+    }
+    let (date, time) = (&timestamp[0..10], &timestamp[11..]);
+    if !["T", "t", " "].contains(&&timestamp[10..11]) {
+        return None;
+    }
+    if &date[4..5] != "-" || &date[7..8] != "-" || &time[2..3] != ":" || &time[5..6] != ":" {
+        return None;
+    }
+    let year: i64 = parse_digits(&date[0..4])?;
+    let month: i64 = parse_digits(&date[5..7])?;
+    let day: i64 = parse_digits(&date[8..10])?;
+    let hours: i64 = parse_digits(&time[0..2])?;
+    let minutes: i64 = parse_digits(&time[3..5])?;
+    let seconds: i64 = parse_digits(&time[6..8])?;
+    if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+        return None;
+    }
+    if hours > 23 || minutes > 59 || seconds > 60 {
+        return None;
+    }
+
+    let mut offset = &time[8..];
+    if let Some(fraction) = offset.strip_prefix('.') {
+        let digits = fraction.chars().take_while(|c| c.is_ascii_digit()).count();
+        if digits == 0 {
+            return None;
+        }
+        offset = &fraction[digits..];
+    }
+    let offset_seconds = match offset {
+        "Z" | "z" => 0,
+        _ if offset.len() == 6 && &offset[3..4] == ":" => {
+            let offset_hours: i64 = parse_digits(&offset[1..3])?;
+            let offset_minutes: i64 = parse_digits(&offset[4..6])?;
+            let offset_seconds = offset_hours * 3600 + offset_minutes * 60;
+            match &offset[0..1] {
+                "+" => offset_seconds,
+                "-" => -offset_seconds,
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+
+    let days = days_from_civil(year, month, day);
+    Some(days * 86400 + hours * 3600 + minutes * 60 + seconds - offset_seconds)
+}
+
+fn parse_digits(digits: &str) -> Option<i64> {
+    if !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Computes the number of days since the Unix epoch of a date, using the
+// algorithm from http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Formats a number of seconds since the Unix epoch as an RFC 3339
+/// date and time in UTC.
+pub fn format_timestamp(timestamp: i64) -> String {
+    let (days, seconds) = (timestamp.div_euclid(86400), timestamp.rem_euclid(86400));
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+// Computes the date of a number of days since the Unix epoch, using the
+// algorithm from http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = if days >= 0 { days } else { days - 146096 } / 146097;
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_parse_epoch() {
+        assert_eq!(parse_timestamp("1647270566"), Ok(1647270566));
+    }
+
+    #[test]
+    pub fn test_parse_rfc3339() {
+        assert_eq!(parse_timestamp("1970-01-01T00:00:00Z"), Ok(0));
+        assert_eq!(parse_timestamp("2022-03-14T15:09:26Z"), Ok(1647270566));
+        assert_eq!(parse_timestamp("2022-03-14T15:09:26.535Z"), Ok(1647270566));
+        assert_eq!(parse_timestamp("2022-03-14T10:09:26-05:00"), Ok(1647270566));
+        assert_eq!(parse_timestamp("2024-02-29T00:00:00+00:00"), Ok(1709164800));
+    }
+
+    #[test]
+    pub fn test_format_timestamp() {
+        assert_eq!(format_timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_timestamp(1647270566), "2022-03-14T15:09:26Z");
+        assert_eq!(format_timestamp(1709164800), "2024-02-29T00:00:00Z");
+        assert_eq!(format_timestamp(-1), "1969-12-31T23:59:59Z");
+    }
+
+    #[test]
+    pub fn test_parse_invalid_timestamp() {
+        assert!(parse_timestamp("2022-03-14").is_err());
+        assert!(parse_timestamp("2022-02-29T00:00:00Z").is_err());
+        assert!(parse_timestamp("2022-03-14T25:09:26Z").is_err());
+        assert!(parse_timestamp("2022-03-14T15:09:26").is_err());
+        assert!(parse_timestamp("2022-03-14T15:09:26.Z").is_err());
+    }
+}
@@ -0,0 +1,124 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::account::Balance;
+use crate::trail::{Decision, TrailEntry};
+
+/// A row touching the account of a customer, with the balances of the
+/// account once the row was processed.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub struct TraceStep {
+    pub entry: TrailEntry,
+
+    /// The balances of the traced account after the row, indexed by currency.
+    pub balances: BTreeMap<String, Balance>,
+}
+impl fmt::Display for TraceStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.entry)?;
+        for (currency, balance) in &self.balances {
+            write!(
+                f,
+                "\n    {}: available {}, held {}, reserved {}, total {}",
+                currency,
+                balance.available.normalize(),
+                balance.held.normalize(),
+                balance.reserved.normalize(),
+                balance.get_total().normalize()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Replays the trail of a customer, as recorded by a `TrailCollector`, from the
+/// balances of the account before the trail, and returns every row with the
+/// balances that followed it.
+pub fn trace(balances: &BTreeMap<String, Balance>, trail: Vec<TrailEntry>) -> Vec<TraceStep> {
+    let mut balances = balances.clone();
+    trail
+        .into_iter()
+        .map(|entry| {
+            if let Decision::Applied { entries } | Decision::Expired { entries } = &entry.decision {
+                for change in entries {
+                    let balance = balances.entry(change.currency.clone()).or_default();
+                    balance.available += change.available;
+                    balance.held += change.held;
+                    balance.reserved += change.reserved;
+                }
+            }
+            TraceStep {
+                entry,
+                balances: balances.clone(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank::Bank;
+    use crate::test_support::{transaction, transfer};
+    use crate::trail::TrailCollector;
+    use crate::transaction::TransactionType;
+
+    #[test]
+    pub fn test_trace() {
+        let mut bank = Bank::new();
+        let collector = TrailCollector::with_clients(vec![2]);
+        bank.add_observer(collector.clone());
+        bank.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some("10.0")));
+        bank.process_transaction(transaction(TransactionType::Deposit, 2, 2, Some("1.0")));
+        bank.process_transaction(transaction(TransactionType::Deposit, 2, 2, Some("1.0")));
+        bank.process_transaction(transfer(1, 3, "4.0", 2));
+        bank.process_transaction(transaction(TransactionType::Dispute, 2, 3, None));
+        bank.process_transaction(transaction(TransactionType::Dispute, 2, 2, None));
+        bank.process_transaction(transaction(TransactionType::Chargeback, 2, 2, None));
+        bank.process_transaction(transaction(TransactionType::Withdrawal, 2, 4, Some("1.0")));
+
+        let steps = trace(&BTreeMap::new(), collector.get_trail(2));
+        let lines: Vec<String> = steps.iter().map(|step| step.to_string()).collect();
+        assert_eq!(lines.len(), 7);
+        assert_eq!(
+            lines[0],
+            "row 2: deposit 2 of client 2: applied (USD available +1)\n    \
+             USD: available 1, held 0, reserved 0, total 1"
+        );
+        assert!(lines[1].starts_with("row 3: deposit 2 of client 2: rejected, duplicate_transaction: "));
+        assert_eq!(
+            lines[2],
+            "row 4: transfer 3 of client 1 to client 2: applied (USD available +4)\n    \
+             USD: available 5, held 0, reserved 0, total 5"
+        );
+        assert!(lines[3].starts_with("row 5: dispute 3 of client 2: rejected, unknown_transaction: "));
+        assert!(lines[5].ends_with("USD: available 4, held 0, reserved 0, total 4"));
+        assert!(lines[6].starts_with("row 8: withdrawal 4 of client 2: rejected, account_locked: "));
+
+        // The replayed balances match the account.
+        let last = &steps[steps.len() - 1].balances["USD"];
+        assert_eq!(*last, bank.accounts[&2].get_balance("USD"));
+    }
+
+    #[test]
+    pub fn test_trace_expiry() {
+        let config = serde_json::from_str(r#"{"authorization_expiry_rows": 2}"#).unwrap();
+        let mut bank = Bank::with_config(config);
+        let collector = TrailCollector::new();
+        bank.add_observer(collector.clone());
+        bank.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some("10.0")));
+        bank.process_transaction(transaction(TransactionType::Authorize, 1, 2, Some("4.0")));
+        bank.process_transaction(transaction(TransactionType::Deposit, 2, 3, Some("1.0")));
+        bank.process_transaction(transaction(TransactionType::Deposit, 1, 4, Some("1.0")));
+
+        let steps = trace(&BTreeMap::new(), collector.get_trail(1));
+        assert_eq!(
+            steps[2].to_string(),
+            "after row 3: authorization 2 of client 1 expired (USD available +4, USD reserved -4)\n    \
+             USD: available 10, held 0, reserved 0, total 10"
+        );
+        assert_eq!(steps[3].balances["USD"], bank.accounts[&1].get_balance("USD"));
+    }
+}
@@ -0,0 +1,272 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+use std::fmt;
+use std::rc::Rc;
+
+use rust_decimal::prelude::*;
+
+use crate::ledger::{EntryKind, LedgerEntry};
+use crate::observer::{Event, Observer};
+use crate::transaction::TransactionType;
+
+/// What the bank decided for a transaction.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub enum Decision {
+    /// The transaction was applied, with the movements of funds that it caused.
+    Applied { entries: Vec<LedgerEntry> },
+    /// The transaction was rejected. The reason is the code of the error.
+    Rejected { reason: String, message: String },
+    /// The funds of an authorization were released because it expired,
+    /// after the row of the entry.
+    Expired { entries: Vec<LedgerEntry> },
+}
+
+/// A transaction processed by the bank, with the decision taken for it.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+pub struct TrailEntry {
+    pub row: u64,
+
+    /// The line of the file holding the transaction, if it was read from a file.
+    pub line: Option<u64>,
+
+    pub client_id: u16,
+
+    pub transaction_id: u32,
+
+    pub r#type: TransactionType,
+
+    /// The account credited by a transfer.
+    pub destination: Option<u16>,
+
+    pub decision: Decision,
+}
+impl TrailEntry {
+    // Gets a copy of the entry with the movements of funds of the account of
+    // a customer only. The credit of the destination of a transfer is its last entry.
+    fn for_client(&self, client_id: u16) -> TrailEntry {
+        let mut entry = self.clone();
+        if let (Decision::Applied { entries }, Some(_)) = (&mut entry.decision, self.destination) {
+            if self.r#type == TransactionType::Transfer && !entries.is_empty() {
+                let destination_entries = entries.split_off(entries.len() - 1);
+                if client_id != self.client_id {
+                    *entries = destination_entries;
+                }
+            }
+        }
+        entry
+    }
+}
+impl fmt::Display for TrailEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Decision::Expired { entries } = &self.decision {
+            let changes: Vec<String> = entries.iter().flat_map(format_entry).collect();
+            return write!(
+                f,
+                "after row {}: authorization {} of client {} expired ({})",
+                self.row,
+                self.transaction_id,
+                self.client_id,
+                changes.join(", ")
+            );
+        }
+        write!(f, "row {}", self.row)?;
+        if let Some(line) = self.line {
+            write!(f, " (line {})", line)?;
+        }
+        write!(
+            f,
+            ": {} {} of client {}",
+            self.r#type.get_name(),
+            self.transaction_id,
+            self.client_id
+        )?;
+        if let Some(destination) = self.destination {
+            write!(f, " to client {}", destination)?;
+        }
+        match &self.decision {
+            Decision::Applied { entries } => {
+                write!(f, ": applied")?;
+                let changes: Vec<String> = entries.iter().flat_map(format_entry).collect();
+                if !changes.is_empty() {
+                    write!(f, " ({})", changes.join(", "))?;
+                }
+                Ok(())
+            }
+            Decision::Rejected { reason, message } => write!(f, ": rejected, {}: {}", reason, message),
+            Decision::Expired { .. } => Ok(()),
+        }
+    }
+}
+
+// Formats the changes of the balances caused by an entry, skipping the
+// balances that did not change.
+fn format_entry(entry: &LedgerEntry) -> Vec<String> {
+    [
+        ("available", entry.available),
+        ("held", entry.held),
+        ("reserved", entry.reserved),
+    ]
+    .iter()
+    .filter(|(_, amount)| !amount.is_zero())
+    .map(|(balance, amount)| {
+        let sign = if amount.is_sign_negative() { "" } else { "+" };
+        format!("{} {} {}{}", entry.currency, balance, sign, amount.normalize())
+    })
+    .collect()
+}
+
+/// Records the transactions processed by the bank, indexed by the
+/// customers they touched: the client of the transaction, and the
+/// destination of a transfer. The collector can be cloned, and the
+/// clones share the same trail.
+#[derive(Clone)]
+#[derive(Default)]
+pub struct TrailCollector {
+    // The customers whose transactions are recorded, or all of them.
+    client_ids: Option<HashSet<u16>>,
+
+    trail: Rc<RefCell<BTreeMap<u16, Vec<TrailEntry>>>>,
+
+    // The transaction being processed, as it was received.
+    received: Option<TrailEntry>,
+
+    // The row of the last transaction received.
+    row: u64,
+}
+impl TrailCollector {
+    pub fn new() -> TrailCollector {
+        TrailCollector::default()
+    }
+
+    /// Creates a collector recording only the transactions touching the given customers.
+    pub fn with_clients(client_ids: impl IntoIterator<Item = u16>) -> TrailCollector {
+        TrailCollector {
+            client_ids: Some(client_ids.into_iter().collect()),
+            ..TrailCollector::default()
+        }
+    }
+
+    /// Gets the transactions that touched a customer, in the order they were
+    /// processed, with the movements of funds of the account of the customer only.
+    pub fn get_trail(&self, client_id: u16) -> Vec<TrailEntry> {
+        self.trail.borrow().get(&client_id).cloned().unwrap_or_default()
+    }
+
+    fn record(&mut self, decision: Decision) {
+        let mut entry = match self.received.take() {
+            Some(entry) => entry,
+            None => return,
+        };
+        entry.decision = decision;
+        self.push(entry);
+    }
+
+    fn push(&mut self, entry: TrailEntry) {
+        let mut trail = self.trail.borrow_mut();
+        for client_id in std::iter::once(entry.client_id).chain(entry.destination) {
+            if self
+                .client_ids
+                .as_ref()
+                .is_none_or(|ids| ids.contains(&client_id))
+            {
+                trail
+                    .entry(client_id)
+                    .or_default()
+                    .push(entry.for_client(client_id));
+            }
+        }
+    }
+}
+impl Observer for TrailCollector {
+    fn notify(&mut self, event: &Event) {
+        match event {
+            Event::Received {
+                row,
+                line,
+                client_id,
+                transaction_id,
+                r#type,
+                destination,
+                ..
+            } => {
+                self.row = *row;
+                self.received = Some(TrailEntry {
+                    row: *row,
+                    line: *line,
+                    client_id: *client_id,
+                    transaction_id: *transaction_id,
+                    r#type: r#type.clone(),
+                    destination: *destination,
+                    decision: Decision::Applied { entries: vec![] },
+                })
+            }
+            Event::Applied { entries, .. } => self.record(Decision::Applied {
+                entries: entries.clone(),
+            }),
+            Event::Rejected { reason, message, .. } => self.record(Decision::Rejected {
+                reason: reason.clone(),
+                message: message.clone(),
+            }),
+            Event::AuthorizationExpired {
+                client_id,
+                transaction_id,
+                currency,
+                amount,
+            } => self.push(TrailEntry {
+                row: self.row,
+                line: None,
+                client_id: *client_id,
+                transaction_id: *transaction_id,
+                r#type: TransactionType::Authorize,
+                destination: None,
+                decision: Decision::Expired {
+                    entries: vec![LedgerEntry {
+                        transaction_id: *transaction_id,
+                        r#type: TransactionType::Authorize,
+                        kind: EntryKind::Expiry,
+                        currency: currency.clone(),
+                        available: *amount,
+                        held: Decimal::ZERO,
+                        reserved: -*amount,
+                    }],
+                },
+            }),
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank::Bank;
+    use crate::test_support::{transaction, transfer};
+
+    #[test]
+    pub fn test_trail() {
+        let mut bank = Bank::new();
+        let collector = TrailCollector::with_clients(vec![2]);
+        bank.add_observer(collector.clone());
+        bank.process_transaction(transaction(TransactionType::Deposit, 1, 1, Some("10.0")));
+        bank.process_transaction(transfer(1, 2, "4.0", 2));
+        bank.process_transaction(transaction(TransactionType::Withdrawal, 2, 3, Some("5.0")));
+
+        assert!(collector.get_trail(1).is_empty());
+        let trail: Vec<String> = collector
+            .get_trail(2)
+            .iter()
+            .map(|entry| entry.to_string())
+            .collect();
+        assert_eq!(trail.len(), 2);
+        assert_eq!(
+            trail[0],
+            "row 2: transfer 2 of client 1 to client 2: applied (USD available +4)"
+        );
+        assert!(trail[1].starts_with("row 3: withdrawal 3 of client 2: rejected, insufficient_funds: "));
+    }
+}
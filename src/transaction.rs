@@ -1,6 +1,7 @@
 use rust_decimal::prelude::*;
-use serde::{Deserialize, Deserializer};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
+#[derive(Serialize)]
 #[derive(Deserialize)]
 #[derive(Debug)]
 #[derive(Clone)]
@@ -15,13 +16,28 @@ pub struct Transaction {
     pub transaction_id: u32,
 
     pub amount: Option<Decimal>,
+
+    /// The currency of the transaction. Inputs without a currency
+    /// column use the default currency.
+    #[serde(default)]
+    pub currency: Option<String>,
+
+    /// The customer receiving the funds of a transfer.
+    #[serde(default)]
+    pub destination: Option<u16>,
+
+    /// The time of the transaction in seconds since the Unix epoch, parsed
+    /// from an RFC 3339 date and time or from a number of seconds. It is
+    /// serialized as a number of seconds, so that any timestamp can be read back.
+    #[serde(default, deserialize_with = "crate::timestamp::deserialize_timestamp")]
+    pub timestamp: Option<i64>,
 }
 impl Transaction {
     pub fn get_type(&self) -> &TransactionType {
         &self.r#type
     }
     pub fn is_disputable(&self) -> bool {
-        return [TransactionType::Deposit, TransactionType::Withdrawal].contains(self.get_type());
+        [TransactionType::Deposit, TransactionType::Withdrawal].contains(self.get_type())
     }
 }
 
@@ -34,6 +50,25 @@ pub enum TransactionType {
     Dispute,
     Resolve,
     Chargeback,
+    /// Moves funds from the account of the customer to the account
+    /// of the destination customer.
+    Transfer,
+    /// Cancels an earlier deposit or withdrawal, fully or partially.
+    Reversal,
+    /// Returns the funds of an earlier withdrawal, fully or partially.
+    Refund,
+    /// Reserves funds for a later capture.
+    Authorize,
+    /// Withdraws the funds reserved by an authorization.
+    Capture,
+    /// Releases the funds reserved by an authorization.
+    Void,
+    /// Interest credited by the bank. Interest transactions are generated
+    /// by the bank, and rejected in the input.
+    Interest,
+    /// Any other type, processed by the handler registered
+    /// in the bank for that type.
+    Custom(String),
 }
 impl TransactionType {
     // TODO this boilerplate code could be replaced by using a macro like
@@ -57,9 +92,56 @@ impl TransactionType {
         if transaction_type == "chargeback" {
             return Ok(TransactionType::Chargeback);
         }
+        if transaction_type == "transfer" {
+            return Ok(TransactionType::Transfer);
+        }
+        if transaction_type == "reversal" {
+            return Ok(TransactionType::Reversal);
+        }
+        if transaction_type == "refund" {
+            return Ok(TransactionType::Refund);
+        }
+        if transaction_type == "authorize" {
+            return Ok(TransactionType::Authorize);
+        }
+        if transaction_type == "capture" {
+            return Ok(TransactionType::Capture);
+        }
+        if transaction_type == "void" {
+            return Ok(TransactionType::Void);
+        }
+        if transaction_type == "interest" {
+            return Ok(TransactionType::Interest);
+        }
+        if !transaction_type.is_empty()
+            && transaction_type
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return Ok(TransactionType::Custom(transaction_type));
+        }
         Err(format!("Invalid transaction type {}.", transaction_type))
     }
 
+    /// Gets the name of the type, as written in the input.
+    pub fn get_name(&self) -> &str {
+        match self {
+            TransactionType::Deposit => "deposit",
+            TransactionType::Withdrawal => "withdrawal",
+            TransactionType::Dispute => "dispute",
+            TransactionType::Resolve => "resolve",
+            TransactionType::Chargeback => "chargeback",
+            TransactionType::Transfer => "transfer",
+            TransactionType::Reversal => "reversal",
+            TransactionType::Refund => "refund",
+            TransactionType::Authorize => "authorize",
+            TransactionType::Capture => "capture",
+            TransactionType::Void => "void",
+            TransactionType::Interest => "interest",
+            TransactionType::Custom(name) => name,
+        }
+    }
+
     pub fn deserialize<'de, D>(deserializer: D) -> Result<TransactionType, D::Error>
     where
         D: Deserializer<'de>,
@@ -68,14 +150,34 @@ impl TransactionType {
 
         match TransactionType::from_string(&buf) {
             Ok(b) => Ok(b),
-            Err(e) => Err(e).map_err(serde::de::Error::custom),
+            Err(e) => Err(serde::de::Error::custom(e)),
         }
     }
 }
 
+impl<'de> Deserialize<'de> for TransactionType {
+    fn deserialize<D>(deserializer: D) -> Result<TransactionType, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        TransactionType::deserialize(deserializer)
+    }
+}
+
+impl Serialize for TransactionType {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.get_name())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::bank::Bank;
+    use crate::observer::{Event, MemorySink};
 
     #[test]
     pub fn test_parse_deposit_transaction() {
@@ -88,12 +190,36 @@ mod tests {
     }
 
     #[test]
-    #[should_panic]
     pub fn test_parse_invalid_transaction_type() {
         let serialized_tx: &str = "invalid,1,1,1.0";
+        let tx: Transaction = deserialize_single_transaction(serialized_tx).unwrap();
+
+        // A type without a handler is rejected when the transaction is processed.
+        let mut bank = Bank::new();
+        let sink = MemorySink::new();
+        bank.add_observer(sink.clone());
+        bank.process_transaction(tx);
+        assert!(sink.get_events().iter().any(|event| matches!(
+            event,
+            Event::Rejected { reason, .. } if reason == "unknown_transaction_type"
+        )));
+        assert_eq!(bank.accounts[&1].get_balance("USD").available, Decimal::ZERO);
+    }
+
+    #[test]
+    #[should_panic]
+    pub fn test_parse_malformed_transaction_type() {
+        let serialized_tx: &str = "in valid,1,1,1.0";
         deserialize_single_transaction(serialized_tx).unwrap();
     }
 
+    #[test]
+    pub fn test_parse_custom_transaction_type() {
+        let serialized_tx: &str = "Adjustment,1,1,1.0";
+        let tx: Transaction = deserialize_single_transaction(serialized_tx).unwrap();
+        assert_eq!(*tx.get_type(), TransactionType::Custom("adjustment".to_string()));
+    }
+
     #[test]
     pub fn test_parse_uppercase_transaction_type() {
         let serialized_tx: &str = "Deposit,1,1,1.0";
@@ -125,18 +251,61 @@ mod tests {
         assert_eq!(tx.amount, Some(Decimal::from_str("3.5545").unwrap()));
     }
 
+    #[test]
+    pub fn test_parse_currency() {
+        let serialized_tx: &str = "deposit, 1, 1, 3.5, EUR";
+        let tx: Transaction =
+            deserialize_single_transaction_with_header("type,client,tx,amount,currency", serialized_tx)
+                .unwrap();
+        assert_eq!(tx.currency, Some("EUR".to_string()));
+
+        let serialized_tx: &str = "deposit, 1, 1, 3.5";
+        let tx: Transaction = deserialize_single_transaction(serialized_tx).unwrap();
+        assert_eq!(tx.currency, None);
+    }
+
+    #[test]
+    pub fn test_parse_transfer() {
+        let serialized_tx: &str = "transfer, 1, 1, 3.5, 2";
+        let tx: Transaction =
+            deserialize_single_transaction_with_header("type,client,tx,amount,destination", serialized_tx)
+                .unwrap();
+        assert_eq!(*tx.get_type(), TransactionType::Transfer);
+        assert_eq!(tx.destination, Some(2));
+    }
+
+    #[test]
+    pub fn test_parse_timestamp() {
+        let header = "type,client,tx,amount,timestamp";
+        let tx = deserialize_single_transaction_with_header(header, "deposit, 1, 1, 3.5, 2022-03-14T15:09:26Z")
+            .unwrap();
+        assert_eq!(tx.timestamp, Some(1647270566));
+
+        let tx = deserialize_single_transaction_with_header(header, "deposit, 1, 1, 3.5, 1647270566").unwrap();
+        assert_eq!(tx.timestamp, Some(1647270566));
+
+        let tx = deserialize_single_transaction_with_header(header, "deposit, 1, 1, 3.5,").unwrap();
+        assert_eq!(tx.timestamp, None);
+    }
+
     pub fn deserialize_single_transaction(serialized_tx: &str) -> Result<Transaction, String> {
-        let header: &str = "type,client,tx,amount";
+        deserialize_single_transaction_with_header("type,client,tx,amount", serialized_tx)
+    }
+
+    pub fn deserialize_single_transaction_with_header(
+        header: &str,
+        serialized_tx: &str,
+    ) -> Result<Transaction, String> {
         let csv_file: String = format!("{}\n{}", header, serialized_tx);
 
         let mut reader = csv::ReaderBuilder::new()
             .has_headers(true)
             .trim(csv::Trim::All)
             .from_reader(csv_file.as_bytes());
-        for result in reader.deserialize() {
+        if let Some(result) = reader.deserialize().next() {
             let tx: Transaction = result.expect("Could not deserialize transaction.");
             return Ok(tx);
         }
-        return Err("Did not deserialize any transaction".to_string());
+        Err("Did not deserialize any transaction".to_string())
     }
 }